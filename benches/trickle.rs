@@ -0,0 +1,94 @@
+//! Parsing a large message arriving in small segments
+//!
+//! `Parse::parse` restarts the parser from the beginning of the buffer after
+//! each refill, so the time grows quadratically with the message size, while
+//! `parse_with_state` resumes where the parser stopped, and stays linear.
+//!
+//! Run with `cargo bench --bench trickle`.
+use nom::{Err, IResult, Needed};
+use nom_bufreader::builder::BufReaderBuilder;
+use nom_bufreader::{Error, Parse};
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+const SEGMENT: usize = 1024;
+
+/// A reader returning at most `SEGMENT` bytes per read, like a socket
+/// receiving a message in small packets.
+struct Trickle<'a> {
+    data: &'a [u8],
+}
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = SEGMENT.min(buf.len()).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+fn find_end(i: &[u8]) -> Option<usize> {
+    memchr::memmem::find(i, b"\r\n\r\n")
+}
+
+fn message(i: &[u8]) -> IResult<&[u8], usize, ()> {
+    match find_end(i) {
+        Some(end) => Ok((&i[end + 4..], end)),
+        None => Err(Err::Incomplete(Needed::Unknown)),
+    }
+}
+
+fn message_resumable<'a>(i: &'a [u8], scanned: &mut usize) -> IResult<&'a [u8], usize, ()> {
+    let start = scanned.saturating_sub(3);
+    match find_end(&i[start..]) {
+        Some(end) => Ok((&i[start + end + 4..], start + end)),
+        None => {
+            *scanned = i.len();
+            Err(Err::Incomplete(Needed::Unknown))
+        }
+    }
+}
+
+fn input(size: usize) -> Vec<u8> {
+    let mut data = vec![b'a'; size];
+    data.extend_from_slice(b"\r\n\r\n");
+    data
+}
+
+fn run(data: &[u8], resumable: bool) -> Duration {
+    let builder = BufReaderBuilder::new().capacity(data.len());
+    let mut reader = builder.build(Trickle { data });
+
+    let start = Instant::now();
+    let len = if resumable {
+        let mut scanned = 0;
+        reader.parse_with_state(&mut scanned, message_resumable)
+    } else {
+        reader.parse(message)
+    };
+    let elapsed = start.elapsed();
+
+    let len: Result<usize, Error<()>> = len;
+    assert_eq!(len.unwrap(), data.len() - 4);
+    elapsed
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>10} {:>14} {:>14}",
+        "size", "segments", "parse", "with state"
+    );
+    for size in [64 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024] {
+        let data = input(size);
+        let restart = run(&data, false);
+        let resume = run(&data, true);
+        println!(
+            "{:>10} {:>10} {:>14?} {:>14?}",
+            size,
+            size / SEGMENT,
+            restart,
+            resume
+        );
+    }
+}
@@ -30,7 +30,7 @@ async fn main() -> Result<(), Error<()>> {
     let mut i = BufReader::new(listener.accept().await?.0);
 
     let m = i.parse(method).await?;
-    let _ = i.parse(space).await?;
+    i.parse(space).await?;
     let p = i.parse(path).await?;
     println!("got method {}, path {}", m, p);
     Ok(())
@@ -0,0 +1,114 @@
+//! A RESP client and server talking to each other in the same process.
+//!
+//! The server understands `PING`, `ECHO`, `SET` and `GET`, and writes its
+//! replies a few bytes at a time, so the client keeps getting incomplete
+//! values. The client reads with a small buffer, and stores a value larger
+//! than it.
+//!
+//! Run with `cargo run --example resp_server --features resp`.
+use futures::io::AsyncWriteExt;
+use nom_bufreader::async_bufreader::BufReader;
+use nom_bufreader::resp::{read_value, RespError, Value};
+use nom_bufreader::Error;
+use std::collections::HashMap;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+fn bulk(s: &[u8]) -> Value {
+    Value::BulkString(s.to_vec())
+}
+
+fn command(args: &[&[u8]]) -> Value {
+    Value::Array(args.iter().map(|arg| bulk(arg)).collect())
+}
+
+fn execute(store: &mut HashMap<Vec<u8>, Vec<u8>>, cmd: Value) -> Value {
+    let args = match cmd {
+        Value::Array(args) => args,
+        _ => return Value::Error("ERR expected an array".to_string()),
+    };
+    let args: Vec<&[u8]> = args
+        .iter()
+        .filter_map(|arg| match arg {
+            Value::BulkString(s) => Some(&s[..]),
+            _ => None,
+        })
+        .collect();
+
+    match &args[..] {
+        [cmd] if cmd.eq_ignore_ascii_case(b"ping") => Value::SimpleString("PONG".to_string()),
+        [cmd, msg] if cmd.eq_ignore_ascii_case(b"echo") => bulk(msg),
+        [cmd, key, value] if cmd.eq_ignore_ascii_case(b"set") => {
+            store.insert(key.to_vec(), value.to_vec());
+            Value::SimpleString("OK".to_string())
+        }
+        [cmd, key] if cmd.eq_ignore_ascii_case(b"get") => match store.get(*key) {
+            Some(value) => bulk(value),
+            None => Value::Null,
+        },
+        _ => Value::Error("ERR unknown command".to_string()),
+    }
+}
+
+async fn serve(stream: TcpStream) -> Result<(), Error<RespError>> {
+    let mut connection = BufReader::new(stream.compat());
+    let mut store = HashMap::new();
+
+    loop {
+        let cmd = match read_value(&mut connection).await {
+            Ok(cmd) => cmd,
+            // the client closed the connection
+            Err(Error::Eof) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let mut reply = Vec::new();
+        execute(&mut store, cmd).encode(&mut reply);
+        // writes go to the underlying stream
+        for part in reply.chunks(7) {
+            connection.write_all(part).await?;
+            connection.flush().await?;
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+async fn call(
+    connection: &mut BufReader<tokio_util::compat::Compat<TcpStream>>,
+    args: &[&[u8]],
+) -> Result<Value, Error<RespError>> {
+    let mut request = Vec::new();
+    command(args).encode(&mut request);
+    connection.write_all(&request).await?;
+    read_value(connection).await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error<RespError>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        serve(stream).await
+    });
+
+    let stream = TcpStream::connect(addr).await?;
+    let mut connection = BufReader::with_capacity(64, stream.compat());
+
+    let large = vec![b'x'; 100_000];
+    println!("{:?}", call(&mut connection, &[b"PING"]).await?);
+    println!("{:?}", call(&mut connection, &[b"ECHO", b"hello"]).await?);
+    println!(
+        "{:?}",
+        call(&mut connection, &[b"SET", b"large", &large]).await?
+    );
+    match call(&mut connection, &[b"GET", b"large"]).await? {
+        Value::BulkString(value) => println!("got {} bytes back", value.len()),
+        other => println!("unexpected reply {:?}", other),
+    }
+    println!("{:?}", call(&mut connection, &[b"GET", b"missing"]).await?);
+    println!("{:?}", call(&mut connection, &[b"FLUSHALL"]).await?);
+
+    drop(connection);
+    server.await.expect("the server panicked")
+}
@@ -29,7 +29,7 @@ fn main() -> Result<(), Error<()>> {
     let mut i = BufReader::new(listener.incoming().next().unwrap()?);
 
     let m = i.parse(method)?;
-    let _ = i.parse(space)?;
+    i.parse(space)?;
     let p = i.parse(path)?;
     println!("got method {}, path {}", m, p);
     Ok(())
@@ -31,7 +31,7 @@ async fn main() -> Result<(), Error<()>> {
     let mut i = BufReader::new(listener.accept().await?.0.compat());
 
     let m = i.parse(method).await?;
-    let _ = i.parse(space).await?;
+    i.parse(space).await?;
     let p = i.parse(path).await?;
     println!("got method {}, path {}", m, p);
     Ok(())
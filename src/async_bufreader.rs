@@ -1,7 +1,18 @@
+use super::buffer::Buffer;
 use super::bufreader::DEFAULT_BUF_SIZE;
-use futures::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite, IoSliceMut, SeekFrom};
+use super::builder::Config;
+use super::prefix::PrefixKind;
+use super::replay::Replay;
+use super::Error;
+use futures::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWrite, IoSliceMut,
+    SeekFrom,
+};
 use futures::ready;
 use futures::task::{Context, Poll};
+use memchr::memmem;
+use nom::error::{ErrorKind, ParseError};
+use nom::{Err, IResult, Offset, Parser};
 use pin_project_lite::pin_project;
 use std::io::{self, Read};
 use std::pin::Pin;
@@ -33,9 +44,7 @@ pin_project! {
     pub struct BufReader<R> {
         #[pin]
         inner: R,
-        buffer: Vec<u8>,
-        pos: usize,
-        cap: usize,
+        buffer: Buffer,
     }
 }
 
@@ -48,12 +57,27 @@ impl<R: AsyncRead> BufReader<R> {
 
     /// Creates a new `BufReader` with the specified buffer capacity.
     pub fn with_capacity(capacity: usize, inner: R) -> Self {
-        let buffer = vec![0; capacity];
+        Self::with_config(Config::with_capacity(capacity), inner)
+    }
+
+    /// Creates a new `BufReader` with a default buffer capacity, holding
+    /// `data` in its buffer.
+    ///
+    /// This is useful when some data was already read from `inner` by another
+    /// component: it will be parsed before anything new is read. If `data` is
+    /// larger than the default capacity, the buffer is created large enough
+    /// to hold it.
+    pub fn with_initial_data(inner: R, data: Vec<u8>) -> Self {
+        Self {
+            inner,
+            buffer: Buffer::with_data(Config::with_capacity(DEFAULT_BUF_SIZE), data),
+        }
+    }
+
+    pub(crate) fn with_config(config: Config, inner: R) -> Self {
         Self {
             inner,
-            buffer,
-            pos: 0,
-            cap: 0,
+            buffer: Buffer::new(config),
         }
     }
 
@@ -85,23 +109,420 @@ impl<R: AsyncRead> BufReader<R> {
     ///
     /// Note that this may discard intermediate state of this combinator, so
     /// care should be taken to avoid losing resources when this is called.
+    /// Use [`into_parts`](BufReader::into_parts) or
+    /// [`into_inner_with_buffer`](BufReader::into_inner_with_buffer) to keep
+    /// the buffered data.
     pub fn into_inner(self) -> R {
         self.inner
     }
 
+    /// Consumes this combinator, returning the underlying sink or stream and
+    /// the data that was buffered but not consumed yet.
+    pub fn into_parts(mut self) -> (R, Vec<u8>) {
+        let buffered = self.buffer.take_data();
+        (self.inner, buffered)
+    }
+
+    /// Consumes this combinator, returning the underlying sink or stream
+    /// wrapped in a [`Replay`] that will return the buffered data before
+    /// reading from it.
+    ///
+    /// See the [`replay`](crate::replay) module.
+    pub fn into_inner_with_buffer(self) -> Replay<R> {
+        let (inner, buffered) = self.into_parts();
+        Replay::new(buffered, inner)
+    }
+
     /// Returns a reference to the internally buffered data.
     ///
     /// Unlike `fill_buf`, this will not attempt to fill the buffer if it is empty.
     pub fn buffer(&self) -> &[u8] {
-        &self.buffer[self.pos..self.cap]
+        self.buffer.data()
+    }
+
+    /// Returns the number of bytes the internal buffer can hold at once.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Returns the number of bytes the internal buffer can grow to, when a
+    /// parser needs more data than it currently holds.
+    ///
+    /// This is the same as [`capacity`](BufReader::capacity), unless the
+    /// reader was created with a [`BufReaderBuilder`](crate::builder::BufReaderBuilder).
+    pub fn max_capacity(&self) -> usize {
+        self.buffer.max_capacity()
+    }
+
+    /// Shrinks the internal buffer back to its initial capacity, or to the
+    /// size of the buffered data if it is larger.
+    ///
+    /// This is useful after a large message made the buffer grow, see
+    /// [`ShrinkPolicy`](crate::builder::ShrinkPolicy) to do it automatically.
+    pub fn shrink_to_fit(&mut self) {
+        self.buffer.shrink_to_fit();
+    }
+
+    /// Returns the number of bytes consumed from the stream, if position
+    /// tracking was enabled with
+    /// [`BufReaderBuilder::track_position`](crate::builder::BufReaderBuilder::track_position).
+    pub fn position(&self) -> Option<u64> {
+        self.buffer.position()
     }
 
     /// Invalidates all data in the internal buffer.
     #[inline]
     fn discard_buffer(self: Pin<&mut Self>) {
-        let this = self.project();
-        *this.pos = 0;
-        *this.cap = 0;
+        self.project().buffer.discard();
+    }
+}
+
+impl<R: AsyncRead + Unpin> BufReader<R> {
+    /// Skips the next `n` bytes of the stream.
+    ///
+    /// The buffered data is consumed first, then the remaining data is read
+    /// and discarded, so it does not need to fit in the buffer. If the
+    /// stream ends before `n` bytes were skipped, this returns
+    /// [`Error::Eof`].
+    ///
+    /// For seekable streams, [`skip_seek`](BufReader::skip_seek) avoids
+    /// reading the data.
+    pub async fn skip<E>(&mut self, n: u64) -> Result<(), Error<E>> {
+        let mut remaining = self.buffer.consume_up_to(n);
+        while remaining > 0 {
+            if self.fill_buf().await?.is_empty() {
+                return Err(Error::Eof);
+            }
+            remaining = self.buffer.consume_up_to(remaining);
+        }
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> BufReader<R> {
+    /// Creates a reader returning the next `n` bytes of the stream.
+    ///
+    /// The returned [`Take`] first returns the buffered data, then reads
+    /// directly from the underlying reader, so a large payload following a
+    /// parsed header can be copied elsewhere without fitting in the buffer.
+    /// Once it is done, the `BufReader` can be used again to parse what comes
+    /// after the payload.
+    pub fn take_reader(&mut self, n: u64) -> Take<'_, R> {
+        Take {
+            reader: self,
+            limit: n,
+        }
+    }
+
+    /// Reads exactly enough bytes to fill `buf`.
+    ///
+    /// The buffered data is used first, then large remainders are read
+    /// directly from the underlying reader into `buf`. If the stream ends
+    /// before `buf` is filled, this returns [`Error::Eof`], and the content
+    /// of `buf` is unspecified.
+    pub async fn read_exact_into<E>(&mut self, buf: &mut [u8]) -> Result<(), Error<E>> {
+        use futures::io::AsyncReadExt;
+
+        let mut take = self.take_reader(buf.len() as u64);
+        let mut filled = 0;
+        while filled < buf.len() {
+            match take.read(&mut buf[filled..]).await? {
+                0 => return Err(Error::Eof),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the next `n` bytes of the stream, appending them to `buf`.
+    ///
+    /// The buffered data is used first, then large remainders are read
+    /// directly from the underlying reader into `buf`. If the stream ends
+    /// before `n` bytes were read, this returns [`Error::Eof`], and `buf` is
+    /// left as it was.
+    pub async fn parse_take<E>(&mut self, n: u64, buf: &mut Vec<u8>) -> Result<(), Error<E>> {
+        use futures::io::AsyncReadExt;
+
+        let start = buf.len();
+        match self.take_reader(n).read_to_end(buf).await {
+            Ok(read) if read as u64 == n => Ok(()),
+            Ok(_) => {
+                buf.truncate(start);
+                Err(Error::Eof)
+            }
+            Err(e) => {
+                buf.truncate(start);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Reads data until the `delim` sequence of bytes, without a parser.
+    ///
+    /// This consumes the data up to and including the delimiter, and returns
+    /// the data before the delimiter. When the buffer is refilled, only the
+    /// new data is searched.
+    ///
+    /// If no delimiter is found after `max_len` bytes, this returns
+    /// [`Error::TooLarge`] without consuming anything. It is also returned
+    /// when the buffer reaches its maximum capacity first, so `max_len` plus
+    /// the delimiter should fit in it. If the stream ends before a delimiter is
+    /// found, this returns [`Error::Eof`].
+    pub async fn read_until_delim<E>(
+        &mut self,
+        delim: &[u8],
+        max_len: usize,
+    ) -> Result<&[u8], Error<E>> {
+        let finder = memmem::Finder::new(delim);
+        let mut scanned = 0;
+        loop {
+            if let Some(range) = self.buffer.consume_until(&finder, max_len, &mut scanned)? {
+                return Ok(self.buffer.slice(range));
+            }
+
+            let len = self.buffer.len();
+            if len == self.max_capacity() {
+                return Err(Error::TooLarge);
+            }
+            if self.fill_buf().await?.len() == len {
+                return Err(Error::Eof);
+            }
+        }
+    }
+
+    /// Applies a parser that keeps some state between attempts.
+    ///
+    /// The parser receives `state` along with the buffered data, to store
+    /// how far it already scanned and resume from there after the buffer is
+    /// refilled, instead of scanning the whole message again. The data passed
+    /// to the parser always starts at the same point of the stream until it
+    /// succeeds, so offsets stored in the state stay valid across refills.
+    ///
+    /// The state is owned by the caller, and is left as is when the parser
+    /// succeeds: it should be reset before parsing the next message. If the
+    /// returned future is dropped, or returns an IO error, calling this again
+    /// with the same state resumes parsing. See
+    /// [`bufreader::BufReader::parse_with_state`](crate::bufreader::BufReader::parse_with_state)
+    /// for an example.
+    pub async fn parse_with_state<S, O, E, P>(
+        &mut self,
+        state: &mut S,
+        mut p: P,
+    ) -> Result<O, Error<E>>
+    where
+        for<'a> P: FnMut(&'a [u8], &mut S) -> IResult<&'a [u8], O, E>,
+    {
+        loop {
+            match p(self.buffer.data(), state) {
+                Err(Err::Error(e)) => return Err(Error::Error(e)),
+                Err(Err::Failure(e)) => return Err(Error::Failure(e)),
+                Err(Err::Incomplete(_)) => {}
+                Ok((i, o)) => {
+                    let offset = self.buffer.data().offset(i);
+                    self.buffer.consume(offset);
+                    return Ok(o);
+                }
+            }
+
+            let len = self.buffer.len();
+            if self.fill_buf().await?.len() == len {
+                return Err(Error::Eof);
+            }
+        }
+    }
+
+    /// Parses a length prefixed frame: reads a length in the `kind` format,
+    /// makes sure the whole frame is in the buffer, then applies `inner` on
+    /// exactly the payload.
+    ///
+    /// If the length is larger than `max_len`, or if the frame cannot fit in
+    /// the maximum capacity of the buffer, this returns [`Error::TooLarge`]
+    /// before reading the payload, and without consuming anything.
+    ///
+    /// `inner` runs in complete mode: `Incomplete` is converted to an error
+    /// of kind `ErrorKind::Complete`. Once it succeeds, the whole frame is
+    /// consumed. If it fails, nothing is consumed. See
+    /// [`bufreader::BufReader::parse_length_prefixed`](crate::bufreader::BufReader::parse_length_prefixed).
+    pub async fn parse_length_prefixed<O, E, P>(
+        &mut self,
+        kind: PrefixKind,
+        max_len: usize,
+        mut inner: P,
+    ) -> Result<O, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], O, E>,
+        for<'a> E: ParseError<&'a [u8]>,
+    {
+        let (prefix_len, len) = loop {
+            match kind.parse::<()>(self.buffer.data()) {
+                Ok((rest, len)) => break (self.buffer.data().offset(rest), len),
+                Err(Err::Incomplete(_)) => self.fill_more().await?,
+                Err(_) => return Err(Error::TooLarge),
+            }
+        };
+
+        if len > max_len as u64 || prefix_len + len as usize > self.buffer.max_capacity() {
+            return Err(Error::TooLarge);
+        }
+        let frame_len = prefix_len + len as usize;
+        while self.buffer.len() < frame_len {
+            self.fill_more().await?;
+        }
+
+        let payload = &self.buffer.data()[prefix_len..frame_len];
+        let o = match inner.parse(payload) {
+            Ok((_, o)) => o,
+            Err(Err::Error(e)) => return Err(Error::Error(e)),
+            Err(Err::Failure(e)) => return Err(Error::Failure(e)),
+            Err(Err::Incomplete(_)) => {
+                return Err(Error::Error(E::from_error_kind(
+                    payload,
+                    ErrorKind::Complete,
+                )))
+            }
+        };
+        self.buffer.consume(frame_len);
+        Ok(o)
+    }
+
+    /// Reads more data in the buffer, returning `Error::Eof` if the stream
+    /// ended.
+    async fn fill_more<E>(&mut self) -> Result<(), Error<E>> {
+        let len = self.buffer.len();
+        if self.fill_buf().await?.len() == len {
+            return Err(Error::Eof);
+        }
+        Ok(())
+    }
+}
+
+/// Reader adapter returning a limited number of bytes from a [`BufReader`].
+///
+/// This struct is created by [`BufReader::take_reader`].
+pub struct Take<'a, R> {
+    reader: &'a mut BufReader<R>,
+    limit: u64,
+}
+
+impl<'a, R> Take<'a, R> {
+    /// Returns the number of bytes that can be read before this instance
+    /// returns EOF.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the `BufReader` this was created from, to continue parsing
+    /// after the data that was read.
+    pub fn into_inner(self) -> &'a mut BufReader<R> {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Take<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.limit == 0 {
+            return Poll::Ready(Ok(0));
+        }
+
+        let max = cmp::min(buf.len() as u64, this.limit) as usize;
+        let nread = if this.reader.buffer.is_empty() {
+            ready!(Pin::new(&mut *this.reader).poll_read(cx, &mut buf[..max]))?
+        } else {
+            // use the buffered data without trying to read more
+            let data = this.reader.buffer.data();
+            let n = cmp::min(max, data.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            this.reader.buffer.consume(n);
+            n
+        };
+        this.limit -= nread as u64;
+        Poll::Ready(Ok(nread))
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for Take<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Take")
+            .field("reader", &self.reader)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> BufReader<R> {
+    /// Skips the next `n` bytes of the stream, seeking in the underlying
+    /// reader instead of reading the data that is not buffered yet.
+    ///
+    /// If the stream ends before `n` bytes were skipped, the reader is left
+    /// at the end of the stream and this returns [`Error::Eof`].
+    pub async fn skip_seek<E>(&mut self, n: u64) -> Result<(), Error<E>> {
+        let remaining = self.buffer.consume_up_to(n);
+        if remaining == 0 {
+            return Ok(());
+        }
+
+        let current = self.inner.seek(SeekFrom::Current(0)).await?;
+        let end = self.inner.seek(SeekFrom::End(0)).await?;
+        let available = end.saturating_sub(current);
+        self.buffer.discard();
+
+        if available < remaining {
+            self.buffer.advance_position(available);
+            return Err(Error::Eof);
+        }
+
+        self.inner
+            .seek(SeekFrom::Start(current + remaining))
+            .await?;
+        self.buffer.advance_position(remaining);
+        Ok(())
+    }
+
+    /// Seeks relative to the current position. If the new position lies within the buffer,
+    /// the buffer will not be flushed, allowing for more efficient seeks.
+    /// This method does not return the location of the underlying reader, so the caller
+    /// must track this information themselves if it is required.
+    pub async fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
+        let pos = self.buffer.pos() as u64;
+        if offset < 0 {
+            if let Some(new_pos) = pos.checked_sub(offset.unsigned_abs()) {
+                self.buffer.set_pos(new_pos as usize);
+                return Ok(());
+            }
+        } else if let Some(new_pos) = pos.checked_add(offset as u64) {
+            if new_pos <= self.buffer.cap() as u64 {
+                self.buffer.set_pos(new_pos as usize);
+                return Ok(());
+            }
+        }
+        self.seek(SeekFrom::Current(offset)).await.map(drop)
+    }
+
+    /// Returns the current seek position from the start of the stream.
+    ///
+    /// The value returned is equivalent to `self.seek(SeekFrom::Current(0))`
+    /// but does not flush the internal buffer. Due to this optimization the
+    /// function does not guarantee that calling `.into_inner()` immediately
+    /// afterwards will yield the underlying reader at the same position. Use
+    /// `seek` instead if you require that guarantee.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the position of the inner reader is smaller
+    /// than the amount of buffered data. That can happen if the inner reader
+    /// has an incorrect implementation of `AsyncSeek`, or if the position has
+    /// gone out of sync due to seeking directly on the underlying reader.
+    pub async fn stream_position(&mut self) -> io::Result<u64> {
+        let remainder = self.buffer.len() as u64;
+        let pos = self.inner.seek(SeekFrom::Current(0)).await?;
+        Ok(pos
+            .checked_sub(remainder)
+            .expect("overflow when subtracting remaining buffer size from inner stream position"))
     }
 }
 
@@ -114,9 +535,13 @@ impl<R: AsyncRead> AsyncRead for BufReader<R> {
         // If we don't have any buffered data and we're doing a massive read
         // (larger than our internal buffer), bypass our internal buffer
         // entirely.
-        if self.pos == self.cap && buf.len() >= self.buffer.len() {
+        if self.buffer.can_bypass() && buf.len() >= self.buffer.capacity() {
             let res = ready!(self.as_mut().project().inner.poll_read(cx, buf));
-            self.discard_buffer();
+            let this = self.project();
+            this.buffer.discard();
+            if let Ok(nread) = res {
+                this.buffer.advance_position(nread as u64);
+            }
             return Poll::Ready(res);
         }
         let mut rem = ready!(self.as_mut().poll_fill_buf(cx))?;
@@ -131,9 +556,13 @@ impl<R: AsyncRead> AsyncRead for BufReader<R> {
         bufs: &mut [IoSliceMut<'_>],
     ) -> Poll<io::Result<usize>> {
         let total_len = bufs.iter().map(|b| b.len()).sum::<usize>();
-        if self.pos == self.cap && total_len >= self.buffer.len() {
+        if self.buffer.can_bypass() && total_len >= self.buffer.capacity() {
             let res = ready!(self.as_mut().project().inner.poll_read_vectored(cx, bufs));
-            self.discard_buffer();
+            let this = self.project();
+            this.buffer.discard();
+            if let Ok(nread) = res {
+                this.buffer.advance_position(nread as u64);
+            }
             return Poll::Ready(res);
         }
         let mut rem = ready!(self.as_mut().poll_fill_buf(cx))?;
@@ -141,44 +570,22 @@ impl<R: AsyncRead> AsyncRead for BufReader<R> {
         self.consume(nread);
         Poll::Ready(Ok(nread))
     }
-
-    // we can't skip unconditionally because of the large buffer case in read.
-    #[cfg(feature = "read-initializer")]
-    unsafe fn initializer(&self) -> Initializer {
-        self.inner.initializer()
-    }
 }
 
 impl<R: AsyncRead> AsyncBufRead for BufReader<R> {
     fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
         let this = self.project();
 
-        if *this.cap == this.buffer.len() {
-            if *this.pos == 0 {
-                return Poll::Ready(Err(io::Error::new(
-                    io::ErrorKind::Interrupted,
-                    "buffer completely filled",
-                )));
-            } else {
-                // reset buffer position
-                if *this.cap - *this.pos > 0 {
-                    for i in 0..(*this.cap - *this.pos) {
-                        this.buffer[i] = this.buffer[*this.pos + i];
-                    }
-                }
-                *this.cap = *this.cap - *this.pos;
-                *this.pos = 0;
-            }
+        if !this.buffer.is_eof() {
+            let read = ready!(this.inner.poll_read(cx, this.buffer.prepare_fill()?))?;
+            this.buffer.filled(read);
         }
 
-        let read = ready!(this.inner.poll_read(cx, this.buffer))?;
-        *this.cap += read;
-
-        Poll::Ready(Ok(&this.buffer[*this.pos..*this.cap]))
+        Poll::Ready(Ok(this.buffer.data()))
     }
 
     fn consume(self: Pin<&mut Self>, amt: usize) {
-        *self.project().pos = cmp::min(self.pos + amt, self.cap);
+        self.project().buffer.consume(amt);
     }
 }
 
@@ -217,7 +624,7 @@ impl<R: fmt::Debug> fmt::Debug for BufReader<R> {
             .field("reader", &self.inner)
             .field(
                 "buffer",
-                &format_args!("{}/{}", self.cap - self.pos, self.buffer.len()),
+                &format_args!("{}/{}", self.buffer.len(), self.buffer.capacity()),
             )
             .finish()
     }
@@ -242,6 +649,9 @@ impl<R: AsyncRead + AsyncSeek> AsyncSeek for BufReader<R> {
     /// seeks will be performed instead of one. If the second seek returns
     /// `Err`, the underlying reader will be left at the same position it would
     /// have if you called `seek` with `SeekFrom::Current(0)`.
+    ///
+    /// To seek without discarding the internal buffer, use
+    /// [`BufReader::seek_relative`].
     fn poll_seek(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
@@ -249,7 +659,7 @@ impl<R: AsyncRead + AsyncSeek> AsyncSeek for BufReader<R> {
     ) -> Poll<io::Result<u64>> {
         let result: u64;
         if let SeekFrom::Current(n) = pos {
-            let remainder = (self.cap - self.pos) as i64;
+            let remainder = self.buffer.len() as i64;
             // it should be safe to assume that remainder fits within an i64 as the alternative
             // means we managed to allocate 8 exbibytes and that's absurd.
             // But it's not out of the realm of possibility for some weird underlying reader to
@@ -279,7 +689,78 @@ impl<R: AsyncRead + AsyncSeek> AsyncSeek for BufReader<R> {
             // Seeking with Start/End doesn't care about our buffer length.
             result = ready!(self.as_mut().project().inner.poll_seek(cx, pos))?;
         }
-        self.discard_buffer();
+        self.project().buffer.seeked(result);
         Poll::Ready(Ok(result))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BufReaderBuilder;
+    use futures::executor::block_on;
+
+    fn read_until_delim(
+        data: &[u8],
+        max_capacity: usize,
+        max_len: usize,
+    ) -> Result<Vec<u8>, Error<()>> {
+        let mut reader = BufReaderBuilder::new()
+            .capacity(4)
+            .max_capacity(max_capacity)
+            .build_async(data);
+        block_on(async {
+            reader
+                .read_until_delim(b"\r\n", max_len)
+                .await
+                .map(<[u8]>::to_vec)
+        })
+    }
+
+    #[test]
+    fn read_until_delim_across_refills() {
+        assert_eq!(
+            read_until_delim(b"abcdefghij\r\n", 64, 16).unwrap(),
+            b"abcdefghij"
+        );
+    }
+
+    #[test]
+    fn read_until_delim_straddling_refill() {
+        assert_eq!(read_until_delim(b"abc\r\nd", 64, 16).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn read_until_delim_max_len() {
+        assert_eq!(read_until_delim(b"abcd\r\n", 64, 4).unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn read_until_delim_over_max_len() {
+        assert!(matches!(
+            read_until_delim(b"abcde\r\n", 64, 4),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn read_until_delim_longer_than_max_capacity() {
+        assert!(matches!(
+            read_until_delim(&[b'a'; 32], 8, 16),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn read_until_delim_filling_max_capacity() {
+        assert_eq!(read_until_delim(b"abcdef\r\n", 8, 16).unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn read_until_delim_eof() {
+        assert!(matches!(
+            read_until_delim(b"abc\r", 64, 16),
+            Err(Error::Eof)
+        ));
+    }
+}
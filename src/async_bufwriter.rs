@@ -0,0 +1,185 @@
+//! Asynchronous buffered writer running
+//! [cookie_factory](https://docs.rs/cookie-factory) serializers
+//!
+//! This is the asynchronous version of `bufwriter::BufWriter`, the writing
+//! counterpart of `async_bufreader::BufReader`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use cookie_factory::{bytes::be_u16, combinator::slice, sequence::tuple};
+//! use futures::io::AsyncWriteExt;
+//! use nom_bufreader::async_bufwriter::BufWriter;
+//!
+//! # futures::executor::block_on(async {
+//! let mut writer = BufWriter::new(Vec::new());
+//! writer.serialize(tuple((be_u16(5), slice(b"hello")))).await.unwrap();
+//!
+//! writer.flush().await.unwrap();
+//! assert_eq!(writer.get_ref(), b"\x00\x05hello");
+//! # });
+//! ```
+use super::bufreader::DEFAULT_BUF_SIZE;
+use super::write_buffer::WriteBuffer;
+use cookie_factory::{GenError, SerializeFn};
+use futures::future::poll_fn;
+use futures::io::AsyncWrite;
+use futures::ready;
+use futures::task::{Context, Poll};
+use pin_project_lite::pin_project;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+
+pin_project! {
+    /// Wraps a writer and buffers its output, serializing data with
+    /// `cookie_factory` serializers.
+    ///
+    /// When the `BufWriter` is dropped, the contents of its buffer are
+    /// discarded: `flush` must be called before dropping it, or it can be
+    /// unwrapped with [`BufWriter::into_parts`] to get the pending data back.
+    pub struct BufWriter<W> {
+        #[pin]
+        inner: W,
+        buf: WriteBuffer,
+    }
+}
+
+impl<W: AsyncWrite> BufWriter<W> {
+    /// Creates a new `BufWriter` with a default buffer capacity. The default is
+    /// currently 8 KB, but may change in the future.
+    pub fn new(inner: W) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Creates a new `BufWriter` with the specified buffer capacity.
+    ///
+    /// The buffer can temporarily grow over that capacity to hold a large
+    /// serialized message, then it is written to the underlying writer.
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        Self {
+            inner,
+            buf: WriteBuffer::new(capacity),
+        }
+    }
+
+    /// Writes all the buffered data to the underlying writer, without
+    /// flushing it.
+    fn poll_flush_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        while !this.buf.is_empty() {
+            match ready!(this.inner.as_mut().poll_write(cx, this.buf.data()))? {
+                0 => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the buffered data",
+                    )));
+                }
+                n => this.buf.consume(n),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BufWriter<W> {
+    /// Runs the serializer `f`, appending its output to the buffer, and
+    /// returns the number of bytes it wrote.
+    ///
+    /// The buffer grows as needed to hold the serialized data. Once it
+    /// reaches its capacity, it is written to the underlying writer. If the
+    /// serializer fails, the data it wrote is discarded, and the data
+    /// buffered before is kept. IO errors are returned as
+    /// `GenError::IoError`.
+    pub async fn serialize<F>(&mut self, f: F) -> Result<usize, GenError>
+    where
+        F: SerializeFn<Vec<u8>>,
+    {
+        let n = self.buf.serialize(&f)?;
+        if self.buf.is_full() {
+            poll_fn(|cx| Pin::new(&mut *self).poll_flush_buf(cx)).await?;
+        }
+        Ok(n)
+    }
+}
+
+impl<W> BufWriter<W> {
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut W> {
+        self.project().inner
+    }
+
+    /// Returns a reference to the buffered data that was not written yet.
+    pub fn buffer(&self) -> &[u8] {
+        self.buf.data()
+    }
+
+    /// Returns the number of bytes the buffer holds before it is written to
+    /// the underlying writer.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Unwraps this `BufWriter<W>`, returning the underlying writer and the
+    /// data that was not written yet.
+    pub fn into_parts(mut self) -> (W, Vec<u8>) {
+        let data = self.buf.take_data();
+        (self.inner, data)
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for BufWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if !self.buf.fits(buf.len()) {
+            ready!(self.as_mut().poll_flush_buf(cx))?;
+        }
+
+        // large writes go directly to the underlying writer
+        if buf.len() >= self.buf.capacity() {
+            self.project().inner.poll_write(cx, buf)
+        } else {
+            self.project().buf.write(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush_buf(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush_buf(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+impl<W: fmt::Debug> fmt::Debug for BufWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufWriter")
+            .field("writer", &self.inner)
+            .field(
+                "buffer",
+                &format_args!("{}/{}", self.buf.data().len(), self.capacity()),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,401 @@
+// buffer management shared by the sync and async readers
+//
+// unlike std's BufReader, data already in the buffer is kept when refilling:
+// it is moved to the beginning of the buffer if needed, and the buffer can
+// grow up to a maximum capacity
+//
+// the memory is allocated without being initialized: the length of the
+// vector is the part of the buffer that was initialized, while `capacity` is
+// the size it can be filled to. The buffer is zeroed progressively, right
+// before reading into it, and only once (like std's `BorrowedBuf`), so
+// creating a reader does not touch its memory, and large buffers only
+// initialize what they actually use
+
+use super::bufreader::DEFAULT_BUF_SIZE;
+use super::builder::{Config, EofPolicy, ShrinkPolicy};
+use super::Error;
+use memchr::memmem;
+use std::cmp;
+use std::io;
+use std::mem;
+use std::ops::Range;
+
+pub(crate) struct Buffer {
+    buf: Vec<u8>,
+    capacity: usize,
+    pos: usize,
+    cap: usize,
+    /// start of the data that must be kept in the buffer even after it was
+    /// consumed, to be able to rewind to it
+    mark: Option<usize>,
+    config: Config,
+    position: Option<u64>,
+    eof: bool,
+}
+
+impl Buffer {
+    pub(crate) fn new(config: Config) -> Buffer {
+        Buffer {
+            buf: allocate(&config, config.capacity),
+            capacity: config.capacity,
+            pos: 0,
+            cap: 0,
+            mark: None,
+            position: if config.track_position { Some(0) } else { None },
+            config,
+            eof: false,
+        }
+    }
+
+    /// Creates a buffer already holding `data`, growing its capacity to fit it
+    /// if needed.
+    pub(crate) fn with_data(mut config: Config, mut data: Vec<u8>) -> Buffer {
+        let len = data.len();
+        config.capacity = cmp::max(config.capacity, len);
+        config.max_capacity = cmp::max(config.max_capacity, len);
+        data.reserve_exact(config.capacity - len);
+        Buffer {
+            buf: data,
+            capacity: config.capacity,
+            pos: 0,
+            cap: len,
+            mark: None,
+            position: if config.track_position { Some(0) } else { None },
+            config,
+            eof: false,
+        }
+    }
+
+    /// Returns the data that was read but not consumed yet.
+    #[inline]
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.buf[self.pos..self.cap]
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos == self.cap
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.cap - self.pos
+    }
+
+    /// Returns `true` if a read can go directly to the underlying reader
+    /// without losing data.
+    #[inline]
+    pub(crate) fn can_bypass(&self) -> bool {
+        self.is_empty() && self.mark.is_none()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn max_capacity(&self) -> usize {
+        self.config.max_capacity
+    }
+
+    /// Raises the maximum capacity to at least `max_capacity`, letting the
+    /// buffer grow to it following the growth policy.
+    #[cfg(feature = "ndjson")]
+    pub(crate) fn reserve_max_capacity(&mut self, max_capacity: usize) {
+        self.config.max_capacity = cmp::max(self.config.max_capacity, max_capacity);
+    }
+
+    pub(crate) fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Returns `true` if the underlying reader reached the end of the stream
+    /// and should not be read from again.
+    pub(crate) fn is_eof(&self) -> bool {
+        self.eof
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        let amt = cmp::min(amt, self.cap - self.pos);
+        self.pos += amt;
+        self.advance_position(amt as u64);
+        self.apply_shrink_policy();
+    }
+
+    /// Searches the buffered data for `delim`, scanning only what was not
+    /// scanned by previous calls, as indicated by `scanned`. If it is found,
+    /// consumes the data up to and including the delimiter, and returns the
+    /// range of the data before the delimiter in the buffer, to be read with
+    /// `slice`.
+    ///
+    /// Returns `TooLarge` if that data is longer than `max_len`, or if the
+    /// delimiter is not found and the data buffered is already longer.
+    pub(crate) fn consume_until<E>(
+        &mut self,
+        finder: &memmem::Finder<'_>,
+        max_len: usize,
+        scanned: &mut usize,
+    ) -> Result<Option<Range<usize>>, Error<E>> {
+        let delim_len = finder.needle().len();
+        let data = self.data();
+        // the delimiter could straddle what was scanned and the new data
+        let start = scanned.saturating_sub(delim_len.saturating_sub(1));
+
+        match finder.find(&data[start..]) {
+            Some(i) => {
+                let len = start + i;
+                if len > max_len {
+                    return Err(Error::TooLarge);
+                }
+
+                // the shrink policy is not applied, to keep the returned
+                // slice valid: it will be on the next call to `consume`
+                let line_start = self.pos;
+                self.pos += len + delim_len;
+                self.advance_position((len + delim_len) as u64);
+                Ok(Some(line_start..line_start + len))
+            }
+            None => {
+                if data.len() >= max_len.saturating_add(delim_len) {
+                    return Err(Error::TooLarge);
+                }
+                *scanned = data.len();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns data from the buffer, consumed or not. The range must come
+    /// from `consume_until`, without filling the buffer in between.
+    pub(crate) fn slice(&self, range: Range<usize>) -> &[u8] {
+        &self.buf[range]
+    }
+
+    /// Consumes up to `amt` bytes of buffered data, returning how many bytes
+    /// remain to be consumed.
+    pub(crate) fn consume_up_to(&mut self, amt: u64) -> u64 {
+        let buffered = cmp::min(amt, self.len() as u64);
+        self.consume(buffered as usize);
+        amt - buffered
+    }
+
+    /// Updates the tracked position for data that was read from the
+    /// underlying reader without going through the buffer.
+    pub(crate) fn advance_position(&mut self, amt: u64) {
+        if let Some(position) = self.position.as_mut() {
+            *position += amt;
+        }
+    }
+
+    /// Moves the current position relative to the buffered data, used to seek
+    /// within the buffer. The caller must make sure the target is in
+    /// `0..=cap`.
+    pub(crate) fn set_pos(&mut self, pos: usize) {
+        if let Some(position) = self.position.as_mut() {
+            if pos >= self.pos {
+                *position += (pos - self.pos) as u64;
+            } else {
+                *position -= (self.pos - pos) as u64;
+            }
+        }
+        self.pos = pos;
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Removes the buffered data, returning it.
+    pub(crate) fn take_data(&mut self) -> Vec<u8> {
+        let data = if self.config.pool.is_some() {
+            // the buffer goes back to the pool
+            self.data().to_vec()
+        } else {
+            let mut buf = mem::take(&mut self.buf);
+            buf.truncate(self.cap);
+            buf.drain(..self.pos);
+            self.capacity = 0;
+            buf
+        };
+        self.discard();
+        data
+    }
+
+    /// Invalidates all data in the buffer.
+    #[inline]
+    pub(crate) fn discard(&mut self) {
+        self.pos = 0;
+        self.cap = 0;
+        self.mark = None;
+    }
+
+    /// Keeps the data from the current position in the buffer, until
+    /// `commit` is called.
+    pub(crate) fn mark(&mut self) {
+        self.mark = Some(self.pos);
+    }
+
+    /// Goes back to the marked position. Returns `false` if there was no
+    /// mark.
+    pub(crate) fn rewind(&mut self) -> bool {
+        match self.mark {
+            Some(mark) => {
+                self.set_pos(mark);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the mark, letting the data before the current position be
+    /// dropped.
+    pub(crate) fn commit(&mut self) {
+        self.mark = None;
+        self.apply_shrink_policy();
+    }
+
+    /// Returns the start of the data that must be kept in the buffer.
+    #[inline]
+    fn start(&self) -> usize {
+        match self.mark {
+            Some(mark) => cmp::min(mark, self.pos),
+            None => self.pos,
+        }
+    }
+
+    /// Invalidates all data in the buffer after the underlying reader was
+    /// moved to `position`.
+    pub(crate) fn seeked(&mut self, position: u64) {
+        self.discard();
+        if let Some(p) = self.position.as_mut() {
+            *p = position;
+        }
+        self.eof = false;
+    }
+
+    /// Returns the part of the buffer where new data can be read, making
+    /// room for it by moving buffered data to the beginning of the buffer,
+    /// or by growing it.
+    pub(crate) fn prepare_fill(&mut self) -> io::Result<&mut [u8]> {
+        if self.can_bypass() {
+            self.discard();
+        }
+
+        if self.cap == self.capacity {
+            if self.start() > 0 {
+                self.reset_position();
+            } else if self.capacity < self.config.max_capacity {
+                let capacity = cmp::max(self.config.growth.grow(self.capacity), self.capacity + 1);
+                self.capacity = cmp::min(capacity, self.config.max_capacity);
+                self.buf.reserve_exact(self.capacity - self.buf.len());
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "buffer completely filled",
+                ));
+            }
+        }
+
+        // make sure there is some initialized space to read into
+        let initialized = cmp::min(self.capacity, self.cap + DEFAULT_BUF_SIZE);
+        if self.buf.len() < initialized {
+            self.buf.resize(initialized, 0);
+        }
+
+        Ok(&mut self.buf[self.cap..])
+    }
+
+    /// Marks `amt` bytes as read in the part returned by `prepare_fill`.
+    pub(crate) fn filled(&mut self, amt: usize) {
+        self.cap += amt;
+        if amt == 0 && self.config.eof == EofPolicy::Sticky {
+            self.eof = true;
+        }
+    }
+
+    /// Moves the buffered data to the beginning of the buffer.
+    fn reset_position(&mut self) {
+        let start = self.start();
+        self.buf.copy_within(start..self.cap, 0);
+        self.move_back(start);
+    }
+
+    /// Updates the offsets after the data starting at `start` was moved to
+    /// the beginning of the buffer.
+    fn move_back(&mut self, start: usize) {
+        self.cap -= start;
+        self.pos -= start;
+        self.mark = self.mark.map(|mark| mark - start);
+    }
+
+    /// Shrinks the buffer back to its initial capacity if it grew, and the
+    /// shrink policy allows it.
+    fn apply_shrink_policy(&mut self) {
+        if self.capacity <= self.config.capacity || self.mark.is_some() {
+            return;
+        }
+
+        let shrink = match self.config.shrink {
+            ShrinkPolicy::Never => false,
+            ShrinkPolicy::ToInitial => self.len() <= self.config.capacity,
+            ShrinkPolicy::WhenEmpty => self.is_empty(),
+        };
+
+        if shrink {
+            self.shrink_to(self.config.capacity);
+        }
+    }
+
+    /// Shrinks the buffer as much as possible, without going under its
+    /// initial capacity or dropping buffered data.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let capacity = cmp::max(self.config.capacity, self.cap - self.start());
+        if capacity < self.capacity {
+            self.shrink_to(capacity);
+        }
+    }
+
+    /// Replaces the buffer with a smaller one holding the buffered data.
+    fn shrink_to(&mut self, capacity: usize) {
+        let start = self.start();
+        let len = self.cap - start;
+        let mut buf = allocate(&self.config, capacity);
+        if buf.len() < len {
+            buf.resize(len, 0);
+        }
+        buf[..len].copy_from_slice(&self.buf[start..self.cap]);
+        self.move_back(start);
+        self.capacity = capacity;
+        release(&self.config, mem::replace(&mut self.buf, buf));
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        release(&self.config, mem::take(&mut self.buf));
+    }
+}
+
+/// Allocates a buffer that can hold `capacity` bytes, from the pool if there
+/// is one. Its length is the part that is already initialized.
+fn allocate(config: &Config, capacity: usize) -> Vec<u8> {
+    match config.pool.as_ref() {
+        Some(pool) => {
+            let mut buf = pool.get(capacity);
+            buf.truncate(capacity);
+            buf.reserve_exact(capacity - buf.len());
+            buf
+        }
+        None => Vec::with_capacity(capacity),
+    }
+}
+
+/// Frees a buffer, or gives it back to the pool if there is one.
+fn release(config: &Config, buf: Vec<u8>) {
+    if let Some(pool) = config.pool.as_ref() {
+        pool.put(buf);
+    }
+}
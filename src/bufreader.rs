@@ -2,6 +2,13 @@
 //
 // this version allows refilling even if the buffer still has some data
 
+use super::buffer::Buffer;
+use super::builder::Config;
+use super::prefix::PrefixKind;
+use super::replay::Replay;
+use memchr::memmem;
+use nom::error::ParseError;
+use nom::{Err, IResult, Offset, Parser};
 use std::cmp;
 use std::fmt;
 use std::io::{self, BufRead, Error, ErrorKind, IoSliceMut, Read, Result, Seek, SeekFrom};
@@ -75,9 +82,7 @@ fn default_read_exact<R: Read + ?Sized>(this: &mut R, mut buf: &mut [u8]) -> Res
 /// ```
 pub struct BufReader<R> {
     inner: R,
-    buf: Vec<u8>,
-    pos: usize,
-    cap: usize,
+    buf: Buffer,
 }
 
 impl<R: Read> BufReader<R> {
@@ -117,12 +122,46 @@ impl<R: Read> BufReader<R> {
     /// }
     /// ```
     pub fn with_capacity(capacity: usize, inner: R) -> BufReader<R> {
-        let buf = vec![0; capacity];
+        BufReader::with_config(Config::with_capacity(capacity), inner)
+    }
+
+    /// Creates a new `BufReader<R>` with a default buffer capacity, holding
+    /// `data` in its buffer.
+    ///
+    /// This is useful when some data was already read from `inner` by another
+    /// component: it will be parsed before anything new is read. If `data` is
+    /// larger than the default capacity, the buffer is created large enough
+    /// to hold it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{bytes::streaming::tag, IResult};
+    /// use nom_bufreader::bufreader::BufReader;
+    /// use nom_bufreader::{Error, Parse};
+    ///
+    /// fn hello(i: &[u8]) -> IResult<&[u8], (), ()> {
+    ///     let (i, _) = tag("hello world")(i)?;
+    ///     Ok((i, ()))
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::with_initial_data(&b" world"[..], b"hello".to_vec());
+    ///     reader.parse(hello)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_initial_data(inner: R, data: Vec<u8>) -> BufReader<R> {
+        BufReader {
+            inner,
+            buf: Buffer::with_data(Config::with_capacity(DEFAULT_BUF_SIZE), data),
+        }
+    }
+
+    pub(crate) fn with_config(config: Config, inner: R) -> BufReader<R> {
         BufReader {
             inner,
-            buf,
-            pos: 0,
-            cap: 0,
+            buf: Buffer::new(config),
         }
     }
 }
@@ -196,7 +235,77 @@ impl<R> BufReader<R> {
     /// }
     /// ```
     pub fn buffer(&self) -> &[u8] {
-        &self.buf[self.pos..self.cap]
+        self.buf.data()
+    }
+
+    /// Marks the current position in the stream, to come back to it later
+    /// with [`rewind`](BufReader::rewind).
+    ///
+    /// Parsers only backtrack within one call to [`Parse::parse`](crate::Parse::parse).
+    /// A mark keeps the data consumed after it in the buffer, even across
+    /// refills, so a sequence of parsers can be attempted, then undone if one
+    /// of them fails. The marked data is kept until
+    /// [`commit`](BufReader::commit) is called, so it must fit in the
+    /// buffer's maximum capacity. Calling `mark` again moves the mark to the
+    /// current position.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{bytes::streaming::tag, IResult};
+    /// use nom_bufreader::bufreader::BufReader;
+    /// use nom_bufreader::{Error, Parse};
+    ///
+    /// fn header(i: &[u8]) -> IResult<&[u8], (), ()> {
+    ///     let (i, _) = tag("HEADER\n")(i)?;
+    ///     Ok((i, ()))
+    /// }
+    ///
+    /// fn v2_body(i: &[u8]) -> IResult<&[u8], u8, ()> {
+    ///     let (i, _) = tag("v2\n")(i)?;
+    ///     Ok((i, 2))
+    /// }
+    ///
+    /// fn v1_message(i: &[u8]) -> IResult<&[u8], u8, ()> {
+    ///     let (i, _) = tag("HEADER\nv1\n")(i)?;
+    ///     Ok((i, 1))
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::new(&b"HEADER\nv1\n"[..]);
+    ///
+    ///     reader.mark();
+    ///     reader.parse(header)?;
+    ///     let version = match reader.parse(v2_body) {
+    ///         Ok(version) => version,
+    ///         Err(_) => {
+    ///             reader.rewind();
+    ///             reader.parse(v1_message)?
+    ///         }
+    ///     };
+    ///     reader.commit();
+    ///
+    ///     assert_eq!(version, 1);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn mark(&mut self) {
+        self.buf.mark();
+    }
+
+    /// Goes back to the position saved by [`mark`](BufReader::mark), making
+    /// the data consumed since then available again.
+    ///
+    /// The mark is kept, so `rewind` can be called multiple times. Returns
+    /// `false`, without moving, if there is no mark.
+    pub fn rewind(&mut self) -> bool {
+        self.buf.rewind()
+    }
+
+    /// Removes the mark set by [`mark`](BufReader::mark), allowing the data
+    /// consumed since then to be dropped from the buffer.
+    pub fn commit(&mut self) {
+        self.buf.commit();
     }
 
     /// Returns the number of bytes the internal buffer can hold at once.
@@ -218,13 +327,93 @@ impl<R> BufReader<R> {
     /// }
     /// ```
     pub fn capacity(&self) -> usize {
-        self.buf.len()
+        self.buf.capacity()
+    }
+
+    /// Returns the number of bytes the internal buffer can grow to, when a
+    /// parser needs more data than it currently holds.
+    ///
+    /// This is the same as [`capacity`](BufReader::capacity), unless the
+    /// reader was created with a [`BufReaderBuilder`](crate::builder::BufReaderBuilder).
+    pub fn max_capacity(&self) -> usize {
+        self.buf.max_capacity()
+    }
+
+    /// Lets the buffer grow to at least `max_capacity` bytes, for users that
+    /// need to hold a message of a known maximum size.
+    #[cfg(feature = "ndjson")]
+    pub(crate) fn reserve_max_capacity(&mut self, max_capacity: usize) {
+        self.buf.reserve_max_capacity(max_capacity);
+    }
+
+    /// Shrinks the internal buffer back to its initial capacity, or to the
+    /// size of the buffered data if it is larger.
+    ///
+    /// This is useful after a large message made the buffer grow, see
+    /// [`ShrinkPolicy`](crate::builder::ShrinkPolicy) to do it automatically.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{bytes::streaming::take, IResult};
+    /// use nom_bufreader::builder::BufReaderBuilder;
+    /// use nom_bufreader::{Error, Parse};
+    ///
+    /// fn large_message(i: &[u8]) -> IResult<&[u8], usize, ()> {
+    ///     let (i, message) = take(100usize)(i)?;
+    ///     Ok((i, message.len()))
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let data = vec![0u8; 100];
+    ///     let mut reader = BufReaderBuilder::new()
+    ///         .capacity(16)
+    ///         .max_capacity(1024)
+    ///         .build(&data[..]);
+    ///
+    ///     reader.parse(large_message)?;
+    ///     assert!(reader.capacity() >= 100);
+    ///
+    ///     reader.shrink_to_fit();
+    ///     assert_eq!(reader.capacity(), 16);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.buf.shrink_to_fit();
+    }
+
+    /// Returns the number of bytes consumed from the stream, if position
+    /// tracking was enabled with
+    /// [`BufReaderBuilder::track_position`](crate::builder::BufReaderBuilder::track_position).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::Read;
+    /// use nom_bufreader::builder::BufReaderBuilder;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///     let mut reader = BufReaderBuilder::new()
+    ///         .track_position(true)
+    ///         .build(&b"hello world"[..]);
+    ///
+    ///     reader.read_exact(&mut [0; 6])?;
+    ///     assert_eq!(reader.position(), Some(6));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn position(&self) -> Option<u64> {
+        self.buf.position()
     }
 
     /// Unwraps this `BufReader<R>`, returning the underlying reader.
     ///
     /// Note that any leftover data in the internal buffer is lost. Therefore,
     /// a following read from the underlying reader may lead to data loss.
+    /// Use [`into_parts`](BufReader::into_parts) or
+    /// [`into_inner_with_buffer`](BufReader::into_inner_with_buffer) to keep
+    /// that data.
     ///
     /// # Examples
     ///
@@ -244,23 +433,39 @@ impl<R> BufReader<R> {
         self.inner
     }
 
-    /// Invalidates all data in the internal buffer.
-    #[inline]
-    fn discard_buffer(&mut self) {
-        self.pos = 0;
-        self.cap = 0;
+    /// Unwraps this `BufReader<R>`, returning the underlying reader and the
+    /// data that was buffered but not consumed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::{BufRead, Read};
+    /// use nom_bufreader::bufreader::BufReader;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///     let mut reader = BufReader::new(&b"hello world"[..]);
+    ///     reader.fill_buf()?;
+    ///     reader.consume(6);
+    ///
+    ///     let (inner, buffered) = reader.into_parts();
+    ///     assert_eq!(buffered, b"world");
+    ///     assert!(inner.is_empty());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn into_parts(mut self) -> (R, Vec<u8>) {
+        let buffered = self.buf.take_data();
+        (self.inner, buffered)
     }
 
-    fn reset_buffer_position(&mut self) {
-        //println!("resetting buffer at pos: {} capacity: {}", self.pos, self.cap);
-        if self.cap - self.pos > 0 {
-            for i in 0..(self.cap - self.pos) {
-                //println!("buf[{}] = buf[{}]", i, self.pos + i);
-                self.buf[i] = self.buf[self.pos + i];
-            }
-        }
-        self.cap = self.cap - self.pos;
-        self.pos = 0;
+    /// Unwraps this `BufReader<R>`, returning the underlying reader wrapped
+    /// in a [`Replay`] that will return the buffered data before reading
+    /// from it.
+    ///
+    /// See the [`replay`](crate::replay) module.
+    pub fn into_inner_with_buffer(self) -> Replay<R> {
+        let (inner, buffered) = self.into_parts();
+        Replay::new(buffered, inner)
     }
 }
 
@@ -270,21 +475,408 @@ impl<R: Seek> BufReader<R> {
     /// This method does not return the location of the underlying reader, so the caller
     /// must track this information themselves if it is required.
     pub fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
-        let pos = self.pos as u64;
+        let pos = self.buf.pos() as u64;
         if offset < 0 {
-            if let Some(new_pos) = pos.checked_sub((-offset) as u64) {
-                self.pos = new_pos as usize;
+            if let Some(new_pos) = pos.checked_sub(offset.unsigned_abs()) {
+                self.buf.set_pos(new_pos as usize);
                 return Ok(());
             }
+        } else if let Some(new_pos) = pos.checked_add(offset as u64) {
+            if new_pos <= self.buf.cap() as u64 {
+                self.buf.set_pos(new_pos as usize);
+                return Ok(());
+            }
+        }
+        self.seek(SeekFrom::Current(offset)).map(drop)
+    }
+}
+
+impl<R: Read> BufReader<R> {
+    /// Skips the next `n` bytes of the stream.
+    ///
+    /// The buffered data is consumed first, then the remaining data is read
+    /// and discarded, so it does not need to fit in the buffer. If the
+    /// stream ends before `n` bytes were skipped, this returns
+    /// [`Error::Eof`](crate::Error::Eof).
+    ///
+    /// For seekable streams, [`skip_seek`](BufReader::skip_seek) avoids
+    /// reading the data.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{bytes::streaming::tag, IResult};
+    /// use nom_bufreader::bufreader::BufReader;
+    /// use nom_bufreader::{Error, Parse};
+    ///
+    /// fn world(i: &[u8]) -> IResult<&[u8], (), ()> {
+    ///     let (i, _) = tag("world")(i)?;
+    ///     Ok((i, ()))
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::with_capacity(5, &b"hello world"[..]);
+    ///     reader.skip(6)?;
+    ///     reader.parse(world)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn skip<E>(&mut self, n: u64) -> std::result::Result<(), crate::Error<E>> {
+        let mut remaining = self.buf.consume_up_to(n);
+        while remaining > 0 {
+            if self.fill_buf()?.is_empty() {
+                return Err(crate::Error::Eof);
+            }
+            remaining = self.buf.consume_up_to(remaining);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> BufReader<R> {
+    /// Creates a reader returning the next `n` bytes of the stream.
+    ///
+    /// The returned [`Take`] first returns the buffered data, then reads
+    /// directly from the underlying reader, so a large payload following a
+    /// parsed header can be copied elsewhere without fitting in the buffer.
+    /// Once it is done, the `BufReader` can be used again to parse what comes
+    /// after the payload.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{character::streaming::digit1, combinator::map_res, IResult};
+    /// use nom::bytes::streaming::tag;
+    /// use nom_bufreader::bufreader::BufReader;
+    /// use nom_bufreader::{Error, Parse};
+    /// use std::str::from_utf8;
+    ///
+    /// fn length(i: &[u8]) -> IResult<&[u8], u64, ()> {
+    ///     let (i, len) = map_res(digit1, |s| from_utf8(s).unwrap().parse::<u64>())(i)?;
+    ///     let (i, _) = tag("\n")(i)?;
+    ///     Ok((i, len))
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::new(&b"5\nhello3\nfoo"[..]);
+    ///
+    ///     let mut out = Vec::new();
+    ///     let len = reader.parse(length)?;
+    ///     std::io::copy(&mut reader.take_reader(len), &mut out)?;
+    ///     assert_eq!(out, b"hello");
+    ///
+    ///     let len = reader.parse(length)?;
+    ///     assert_eq!(len, 3);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn take_reader(&mut self, n: u64) -> Take<'_, R> {
+        Take {
+            reader: self,
+            limit: n,
+        }
+    }
+
+    /// Reads the next `n` bytes of the stream, appending them to `buf`.
+    ///
+    /// The buffered data is used first, then large remainders are read
+    /// directly from the underlying reader into `buf`. If the stream ends
+    /// before `n` bytes were read, this returns [`Error::Eof`](crate::Error::Eof),
+    /// and `buf` is left as it was.
+    pub fn parse_take<E>(
+        &mut self,
+        n: u64,
+        buf: &mut Vec<u8>,
+    ) -> std::result::Result<(), crate::Error<E>> {
+        let start = buf.len();
+        match self.take_reader(n).read_to_end(buf) {
+            Ok(read) if read as u64 == n => Ok(()),
+            Ok(_) => {
+                buf.truncate(start);
+                Err(crate::Error::Eof)
+            }
+            Err(e) => {
+                buf.truncate(start);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Reader adapter returning a limited number of bytes from a [`BufReader`].
+///
+/// This struct is created by [`BufReader::take_reader`].
+pub struct Take<'a, R> {
+    reader: &'a mut BufReader<R>,
+    limit: u64,
+}
+
+impl<'a, R> Take<'a, R> {
+    /// Returns the number of bytes that can be read before this instance
+    /// returns EOF.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the `BufReader` this was created from, to continue parsing
+    /// after the data that was read.
+    pub fn into_inner(self) -> &'a mut BufReader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for Take<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.limit == 0 {
+            return Ok(0);
+        }
+
+        let max = cmp::min(buf.len() as u64, self.limit) as usize;
+        let nread = if self.reader.buf.is_empty() {
+            self.reader.read(&mut buf[..max])?
         } else {
-            if let Some(new_pos) = pos.checked_add(offset as u64) {
-                if new_pos <= self.cap as u64 {
-                    self.pos = new_pos as usize;
-                    return Ok(());
+            // use the buffered data without trying to read more
+            let data = self.reader.buf.data();
+            let n = cmp::min(max, data.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            self.reader.buf.consume(n);
+            n
+        };
+        self.limit -= nread as u64;
+        Ok(nread)
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for Take<'_, R> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Take")
+            .field("reader", &self.reader)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl<R: Read> BufReader<R> {
+    /// Reads data until the `delim` sequence of bytes, without a parser.
+    ///
+    /// This consumes the data up to and including the delimiter, and returns
+    /// the data before the delimiter. The search uses `memchr`, and when the
+    /// buffer is refilled, only the new data is searched.
+    ///
+    /// If no delimiter is found after `max_len` bytes, this returns
+    /// [`Error::TooLarge`](crate::Error::TooLarge) without consuming anything.
+    /// It is also returned when the buffer reaches its maximum capacity first,
+    /// so `max_len` plus the delimiter should fit in it. If the stream ends
+    /// before a delimiter is found, this returns [`Error::Eof`](crate::Error::Eof).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom_bufreader::bufreader::BufReader;
+    /// use nom_bufreader::Error;
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::new(&b"GET / HTTP/1.1\r\nHost: example.com\r\n"[..]);
+    ///
+    ///     assert_eq!(reader.read_until_delim(b"\r\n", 1024)?, b"GET / HTTP/1.1");
+    ///     assert!(matches!(
+    ///         reader.read_until_delim::<()>(b"\r\n", 4),
+    ///         Err(Error::TooLarge)
+    ///     ));
+    ///     assert_eq!(reader.read_until_delim(b"\r\n", 1024)?, b"Host: example.com");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn read_until_delim<E>(
+        &mut self,
+        delim: &[u8],
+        max_len: usize,
+    ) -> std::result::Result<&[u8], crate::Error<E>> {
+        let finder = memmem::Finder::new(delim);
+        let mut scanned = 0;
+        loop {
+            if let Some(range) = self.buf.consume_until(&finder, max_len, &mut scanned)? {
+                return Ok(self.buf.slice(range));
+            }
+
+            let len = self.buf.len();
+            if len == self.max_capacity() {
+                return Err(crate::Error::TooLarge);
+            }
+            if self.fill_buf()?.len() == len {
+                return Err(crate::Error::Eof);
+            }
+        }
+    }
+
+    /// Applies a parser that keeps some state between attempts.
+    ///
+    /// With [`Parse::parse`](crate::Parse::parse), every `Incomplete` result
+    /// restarts the parser at the beginning of the buffered data, so a large
+    /// message arriving in small segments is scanned again after each
+    /// refill. Here the parser also receives `state`, where it can store how
+    /// far it already scanned, or any other progress information, to resume
+    /// from there on the next attempt. The data passed to the parser always
+    /// starts at the same point of the stream until it succeeds, so offsets
+    /// stored in the state stay valid across refills.
+    ///
+    /// The state is owned by the caller, and is left as is when the parser
+    /// succeeds: it should be reset before parsing the next message. When
+    /// this returns an IO error, like `WouldBlock` on a non blocking socket,
+    /// calling it again with the same state resumes parsing.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{Err, IResult, Needed};
+    /// use nom_bufreader::bufreader::BufReader;
+    /// use nom_bufreader::Error;
+    ///
+    /// // finds the end of HTTP headers, without rescanning the data that was
+    /// // already searched
+    /// fn headers<'a>(i: &'a [u8], scanned: &mut usize) -> IResult<&'a [u8], usize, ()> {
+    ///     let start = scanned.saturating_sub(3);
+    ///     match i[start..].windows(4).position(|w| w == b"\r\n\r\n") {
+    ///         Some(end) => Ok((&i[start + end + 4..], start + end)),
+    ///         None => {
+    ///             *scanned = i.len();
+    ///             Err(Err::Incomplete(Needed::Unknown))
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::new(&b"Host: example.com\r\n\r\nbody"[..]);
+    ///
+    ///     let mut scanned = 0;
+    ///     assert_eq!(reader.parse_with_state(&mut scanned, headers)?, 17);
+    ///     assert_eq!(reader.buffer(), b"body");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn parse_with_state<S, O, E, P>(
+        &mut self,
+        state: &mut S,
+        mut p: P,
+    ) -> std::result::Result<O, crate::Error<E>>
+    where
+        for<'a> P: FnMut(&'a [u8], &mut S) -> IResult<&'a [u8], O, E>,
+    {
+        loop {
+            match p(self.buf.data(), state) {
+                Err(Err::Error(e)) => return Err(crate::Error::Error(e)),
+                Err(Err::Failure(e)) => return Err(crate::Error::Failure(e)),
+                Err(Err::Incomplete(_)) => {}
+                Ok((i, o)) => {
+                    let offset = self.buf.data().offset(i);
+                    self.consume(offset);
+                    return Ok(o);
                 }
             }
+
+            let len = self.buf.len();
+            if self.fill_buf()?.len() == len {
+                return Err(crate::Error::Eof);
+            }
         }
-        self.seek(SeekFrom::Current(offset)).map(drop)
+    }
+
+    /// Parses a length prefixed frame: reads a length in the `kind` format,
+    /// makes sure the whole frame is in the buffer, then applies `inner` on
+    /// exactly the payload.
+    ///
+    /// If the length is larger than `max_len`, or if the frame cannot fit in
+    /// the maximum capacity of the buffer, this returns
+    /// [`Error::TooLarge`](crate::Error::TooLarge) before reading the
+    /// payload, and without consuming anything. The buffer grows as needed,
+    /// up to its maximum capacity, see
+    /// [`BufReaderBuilder`](crate::builder::BufReaderBuilder).
+    ///
+    /// `inner` runs in complete mode: all of the payload is available, so
+    /// `Incomplete` is converted to an error of kind `ErrorKind::Complete`.
+    /// Once it succeeds, the whole frame is consumed, even if `inner` did not
+    /// use all of the payload. If it fails, nothing is consumed. See the
+    /// [`prefix`](crate::prefix) module for an example.
+    pub fn parse_length_prefixed<O, E, P>(
+        &mut self,
+        kind: PrefixKind,
+        max_len: usize,
+        mut inner: P,
+    ) -> std::result::Result<O, crate::Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], O, E>,
+        for<'a> E: ParseError<&'a [u8]>,
+    {
+        let (prefix_len, len) = loop {
+            match kind.parse::<()>(self.buf.data()) {
+                Ok((rest, len)) => break (self.buf.data().offset(rest), len),
+                Err(Err::Incomplete(_)) => self.fill_more()?,
+                Err(_) => return Err(crate::Error::TooLarge),
+            }
+        };
+
+        if len > max_len as u64 || prefix_len + len as usize > self.buf.max_capacity() {
+            return Err(crate::Error::TooLarge);
+        }
+        let frame_len = prefix_len + len as usize;
+        while self.buf.len() < frame_len {
+            self.fill_more()?;
+        }
+
+        let payload = &self.buf.data()[prefix_len..frame_len];
+        let o = match inner.parse(payload) {
+            Ok((_, o)) => o,
+            Err(Err::Error(e)) => return Err(crate::Error::Error(e)),
+            Err(Err::Failure(e)) => return Err(crate::Error::Failure(e)),
+            Err(Err::Incomplete(_)) => {
+                return Err(crate::Error::Error(E::from_error_kind(
+                    payload,
+                    nom::error::ErrorKind::Complete,
+                )))
+            }
+        };
+        self.consume(frame_len);
+        Ok(o)
+    }
+
+    /// Reads more data in the buffer, returning `Error::Eof` if the stream
+    /// ended.
+    fn fill_more<E>(&mut self) -> std::result::Result<(), crate::Error<E>> {
+        let len = self.buf.len();
+        if self.fill_buf()?.len() == len {
+            return Err(crate::Error::Eof);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Skips the next `n` bytes of the stream, seeking in the underlying
+    /// reader instead of reading the data that is not buffered yet.
+    ///
+    /// If the stream ends before `n` bytes were skipped, the reader is left
+    /// at the end of the stream and this returns
+    /// [`Error::Eof`](crate::Error::Eof). This discards the mark set by
+    /// [`mark`](BufReader::mark), if any.
+    pub fn skip_seek<E>(&mut self, n: u64) -> std::result::Result<(), crate::Error<E>> {
+        let remaining = self.buf.consume_up_to(n);
+        if remaining == 0 {
+            return Ok(());
+        }
+
+        let current = self.inner.stream_position()?;
+        let end = self.inner.seek(SeekFrom::End(0))?;
+        let available = end.saturating_sub(current);
+        self.buf.discard();
+
+        if available < remaining {
+            self.buf.advance_position(available);
+            return Err(crate::Error::Eof);
+        }
+
+        self.inner.seek(SeekFrom::Start(current + remaining))?;
+        self.buf.advance_position(remaining);
+        Ok(())
     }
 }
 
@@ -293,9 +885,11 @@ impl<R: Read> Read for BufReader<R> {
         // If we don't have any buffered data and we're doing a massive read
         // (larger than our internal buffer), bypass our internal buffer
         // entirely.
-        if self.pos == self.cap && buf.len() >= self.buf.len() {
-            self.discard_buffer();
-            return self.inner.read(buf);
+        if self.buf.can_bypass() && buf.len() >= self.buf.capacity() {
+            self.buf.discard();
+            let nread = self.inner.read(buf)?;
+            self.buf.advance_position(nread as u64);
+            return Ok(nread);
         }
         let nread = {
             let mut rem = self.fill_buf()?;
@@ -321,9 +915,11 @@ impl<R: Read> Read for BufReader<R> {
 
     fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
         let total_len = bufs.iter().map(|b| b.len()).sum::<usize>();
-        if self.pos == self.cap && total_len >= self.buf.len() {
-            self.discard_buffer();
-            return self.inner.read_vectored(bufs);
+        if self.buf.can_bypass() && total_len >= self.buf.capacity() {
+            self.buf.discard();
+            let nread = self.inner.read_vectored(bufs)?;
+            self.buf.advance_position(nread as u64);
+            return Ok(nread);
         }
         let nread = {
             let mut rem = self.fill_buf()?;
@@ -336,24 +932,15 @@ impl<R: Read> Read for BufReader<R> {
 
 impl<R: Read> BufRead for BufReader<R> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        if self.cap == self.buf.len() {
-            if self.pos == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::Interrupted,
-                    "buffer completely filled",
-                ));
-            } else {
-                self.reset_buffer_position();
-            }
+        if !self.buf.is_eof() {
+            let read = self.inner.read(self.buf.prepare_fill()?)?;
+            self.buf.filled(read);
         }
-
-        let read = self.inner.read(&mut self.buf[self.cap..])?;
-        self.cap += read;
-        Ok(&self.buf[self.pos..self.cap])
+        Ok(self.buf.data())
     }
 
     fn consume(&mut self, amt: usize) {
-        self.pos = cmp::min(self.pos + amt, self.cap);
+        self.buf.consume(amt);
     }
 }
 
@@ -366,7 +953,7 @@ where
             .field("reader", &self.inner)
             .field(
                 "buffer",
-                &format_args!("{}/{}", self.cap - self.pos, self.buf.len()),
+                &format_args!("{}/{}", self.buf.len(), self.buf.capacity()),
             )
             .finish()
     }
@@ -398,7 +985,7 @@ impl<R: Seek> Seek for BufReader<R> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         let result: u64;
         if let SeekFrom::Current(n) = pos {
-            let remainder = (self.cap - self.pos) as i64;
+            let remainder = self.buf.len() as i64;
             // it should be safe to assume that remainder fits within an i64 as the alternative
             // means we managed to allocate 8 exbibytes and that's absurd.
             // But it's not out of the realm of possibility for some weird underlying reader to
@@ -409,14 +996,14 @@ impl<R: Seek> Seek for BufReader<R> {
             } else {
                 // seek backwards by our remainder, and then by the offset
                 self.inner.seek(SeekFrom::Current(-remainder))?;
-                self.discard_buffer();
+                self.buf.discard();
                 result = self.inner.seek(SeekFrom::Current(n))?;
             }
         } else {
             // Seeking with Start/End doesn't care about our buffer length.
             result = self.inner.seek(pos)?;
         }
-        self.discard_buffer();
+        self.buf.seeked(result);
         Ok(result)
     }
 
@@ -456,7 +1043,7 @@ impl<R: Seek> Seek for BufReader<R> {
     /// }
     /// ```
     fn stream_position(&mut self) -> io::Result<u64> {
-        let remainder = (self.cap - self.pos) as u64;
+        let remainder = self.buf.len() as u64;
         self.inner.stream_position().map(|pos| {
             pos.checked_sub(remainder).expect(
                 "overflow when subtracting remaining buffer size from inner stream position",
@@ -464,3 +1051,89 @@ impl<R: Seek> Seek for BufReader<R> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BufReaderBuilder;
+    use crate::Error;
+
+    fn reader(data: &[u8], capacity: usize, max_capacity: usize) -> BufReader<&[u8]> {
+        BufReaderBuilder::new()
+            .capacity(capacity)
+            .max_capacity(max_capacity)
+            .build(data)
+    }
+
+    #[test]
+    fn read_until_delim_across_refills() {
+        let mut reader = reader(b"abcdefghij\nk\n", 4, 64);
+        assert_eq!(
+            reader.read_until_delim::<()>(b"\n", 16).unwrap(),
+            b"abcdefghij"
+        );
+        assert_eq!(reader.read_until_delim::<()>(b"\n", 16).unwrap(), b"k");
+    }
+
+    #[test]
+    fn read_until_delim_straddling_refill() {
+        // the first fill stops in the middle of the delimiter
+        let mut reader = reader(b"abc\r\nd\r\n", 4, 64);
+        assert_eq!(reader.read_until_delim::<()>(b"\r\n", 16).unwrap(), b"abc");
+        assert_eq!(reader.read_until_delim::<()>(b"\r\n", 16).unwrap(), b"d");
+    }
+
+    #[test]
+    fn read_until_delim_max_len() {
+        let mut reader = reader(b"abcd\n", 4, 64);
+        assert_eq!(reader.read_until_delim::<()>(b"\n", 4).unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn read_until_delim_over_max_len() {
+        let mut reader = reader(b"abcde\n", 4, 64);
+        assert!(matches!(
+            reader.read_until_delim::<()>(b"\n", 4),
+            Err(Error::TooLarge)
+        ));
+        // nothing was consumed
+        assert_eq!(reader.read_until_delim::<()>(b"\n", 5).unwrap(), b"abcde");
+    }
+
+    #[test]
+    fn read_until_delim_without_delimiter_after_max_len() {
+        let mut reader = reader(&[b'a'; 32], 4, 64);
+        assert!(matches!(
+            reader.read_until_delim::<()>(b"\n", 8),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn read_until_delim_longer_than_max_capacity() {
+        let mut reader = reader(&[b'a'; 32], 4, 8);
+        assert!(matches!(
+            reader.read_until_delim::<()>(b"\n", 16),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn read_until_delim_filling_max_capacity() {
+        let mut reader = reader(b"abcdefg\n", 4, 8);
+        assert_eq!(
+            reader.read_until_delim::<()>(b"\n", 16).unwrap(),
+            b"abcdefg"
+        );
+    }
+
+    #[test]
+    fn read_until_delim_eof() {
+        let mut reader = reader(b"abc\nde", 4, 64);
+        assert_eq!(reader.read_until_delim::<()>(b"\n", 16).unwrap(), b"abc");
+        assert!(matches!(
+            reader.read_until_delim::<()>(b"\n", 16),
+            Err(Error::Eof)
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+//! Buffered writer running [cookie_factory](https://docs.rs/cookie-factory)
+//! serializers
+//!
+//! This is the writing counterpart of `bufreader::BufReader`: where the reader
+//! applies nom parsers to its buffer, [`BufWriter::serialize`] runs
+//! serializers directly into the writer's buffer, and writes it to the
+//! underlying writer once it is full. For asynchronous IO, use
+//! `async_bufwriter::BufWriter`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use cookie_factory::{combinator::string, sequence::tuple, SerializeFn};
+//! use nom::{bytes::streaming::take_until, IResult};
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::bufwriter::BufWriter;
+//! use nom_bufreader::{Error, Parse};
+//! use std::io::Write;
+//! use std::net::TcpStream;
+//!
+//! fn line(i: &[u8]) -> IResult<&[u8], String, ()> {
+//!     let (i, line) = take_until("\r\n")(i)?;
+//!     Ok((&i[2..], String::from_utf8_lossy(line).into_owned()))
+//! }
+//!
+//! fn reply<'a, W: Write + 'a>(line: &'a str) -> impl SerializeFn<W> + 'a {
+//!     tuple((string("echo: "), string(line), string("\r\n")))
+//! }
+//!
+//! fn main() -> Result<(), Error<()>> {
+//!     let stream = TcpStream::connect("127.0.0.1:8080")?;
+//!     let mut reader = BufReader::new(stream.try_clone()?);
+//!     let mut writer = BufWriter::new(stream);
+//!
+//!     loop {
+//!         let l = reader.parse(line)?;
+//!         writer.serialize(reply(&l)).map_err(|_| Error::Error(()))?;
+//!         writer.flush()?;
+//!     }
+//! }
+//! ```
+use super::bufreader::DEFAULT_BUF_SIZE;
+use super::write_buffer::WriteBuffer;
+use cookie_factory::{GenError, SerializeFn};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Wraps a writer and buffers its output, serializing data with
+/// `cookie_factory` serializers.
+///
+/// When the `BufWriter` is dropped, the contents of its buffer are
+/// discarded: [`flush`](Write::flush) must be called before dropping it, or
+/// it can be unwrapped with [`BufWriter::into_parts`] to get the pending
+/// data back.
+pub struct BufWriter<W> {
+    inner: W,
+    buf: WriteBuffer,
+}
+
+impl<W: Write> BufWriter<W> {
+    /// Creates a new `BufWriter` with a default buffer capacity. The default is
+    /// currently 8 KB, but may change in the future.
+    pub fn new(inner: W) -> BufWriter<W> {
+        BufWriter::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Creates a new `BufWriter` with the specified buffer capacity.
+    ///
+    /// The buffer can temporarily grow over that capacity to hold a large
+    /// serialized message, then it is written to the underlying writer.
+    pub fn with_capacity(capacity: usize, inner: W) -> BufWriter<W> {
+        BufWriter {
+            inner,
+            buf: WriteBuffer::new(capacity),
+        }
+    }
+
+    /// Runs the serializer `f`, appending its output to the buffer, and
+    /// returns the number of bytes it wrote.
+    ///
+    /// The buffer grows as needed to hold the serialized data. Once it
+    /// reaches its capacity, it is written to the underlying writer. If the
+    /// serializer fails, the data it wrote is discarded, and the data
+    /// buffered before is kept. IO errors are returned as
+    /// `GenError::IoError`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use cookie_factory::{bytes::be_u16, combinator::slice, sequence::tuple};
+    /// use nom_bufreader::bufwriter::BufWriter;
+    /// use std::io::Write;
+    ///
+    /// let mut writer = BufWriter::new(Vec::new());
+    /// let n = writer.serialize(tuple((be_u16(5), slice(b"hello")))).unwrap();
+    /// assert_eq!(n, 7);
+    ///
+    /// writer.flush().unwrap();
+    /// assert_eq!(writer.get_ref(), b"\x00\x05hello");
+    /// ```
+    pub fn serialize<F>(&mut self, f: F) -> Result<usize, GenError>
+    where
+        F: SerializeFn<Vec<u8>>,
+    {
+        let n = self.buf.serialize(&f)?;
+        if self.buf.is_full() {
+            self.flush_buf()?;
+        }
+        Ok(n)
+    }
+
+    /// Writes all the buffered data to the underlying writer, without
+    /// flushing it.
+    fn flush_buf(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            match self.inner.write(self.buf.data()) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the buffered data",
+                    ));
+                }
+                Ok(n) => self.buf.consume(n),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W> BufWriter<W> {
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns a reference to the buffered data that was not written yet.
+    pub fn buffer(&self) -> &[u8] {
+        self.buf.data()
+    }
+
+    /// Returns the number of bytes the buffer holds before it is written to
+    /// the underlying writer.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Unwraps this `BufWriter<W>`, returning the underlying writer and the
+    /// data that was not written yet.
+    pub fn into_parts(mut self) -> (W, Vec<u8>) {
+        let data = self.buf.take_data();
+        (self.inner, data)
+    }
+}
+
+impl<W: Write> Write for BufWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.buf.fits(buf.len()) {
+            self.flush_buf()?;
+        }
+
+        // large writes go directly to the underlying writer
+        if buf.len() >= self.buf.capacity() {
+            self.inner.write(buf)
+        } else {
+            self.buf.write(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buf()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: fmt::Debug> fmt::Debug for BufWriter<W> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("BufWriter")
+            .field("writer", &self.inner)
+            .field(
+                "buffer",
+                &format_args!("{}/{}", self.buf.data().len(), self.capacity()),
+            )
+            .finish()
+    }
+}
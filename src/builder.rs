@@ -0,0 +1,264 @@
+//! Configuration shared by `bufreader::BufReader`,
+//! `async_bufreader::BufReader` and `bytes_bufreader::BufReader`
+//!
+//! `BufReader::new` and `BufReader::with_capacity` create readers with a
+//! fixed size buffer. A [`BufReaderBuilder`] can describe other settings
+//! once, like how the buffer grows and shrinks, then create any number of
+//! synchronous or asynchronous readers from them.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::builder::{BufReaderBuilder, GrowthPolicy};
+//!
+//! let builder = BufReaderBuilder::new()
+//!     .capacity(4096)
+//!     .max_capacity(1024 * 1024)
+//!     .growth(GrowthPolicy::Double)
+//!     .track_position(true);
+//!
+//! let reader = builder.build(&b"hello"[..]);
+//! assert_eq!(reader.capacity(), 4096);
+//! assert_eq!(reader.max_capacity(), 1024 * 1024);
+//! assert_eq!(reader.position(), Some(0));
+//! ```
+use super::bufreader::{self, DEFAULT_BUF_SIZE};
+use super::pool::BufferPool;
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use super::async_bufreader;
+#[cfg(feature = "bytes")]
+use super::bytes_bufreader;
+#[cfg(feature = "async")]
+use futures::io::AsyncRead;
+
+/// How the buffer grows when a parser needs more data than it can hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    /// Double the capacity of the buffer.
+    Double,
+    /// Add a fixed amount of bytes to the capacity of the buffer.
+    Linear(usize),
+}
+
+impl GrowthPolicy {
+    pub(crate) fn grow(self, capacity: usize) -> usize {
+        match self {
+            GrowthPolicy::Double => capacity.saturating_mul(2),
+            GrowthPolicy::Linear(n) => capacity.saturating_add(n),
+        }
+    }
+}
+
+/// What happens to the buffer after it grew to hold a large message.
+///
+/// Without shrinking, a single large message keeps the largest buffer
+/// allocated for the lifetime of the reader. The policy is applied when data
+/// is consumed, so the memory is released as soon as the large message was
+/// parsed. The buffer can also be shrunk explicitly with the readers'
+/// `shrink_to_fit` method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShrinkPolicy {
+    /// Keep the largest buffer that was needed.
+    Never,
+    /// Go back to the initial capacity once the buffered data fits in it
+    /// again.
+    ToInitial,
+    /// Go back to the initial capacity once all the buffered data was
+    /// consumed. Unlike [`ShrinkPolicy::ToInitial`], this avoids shrinking
+    /// the buffer while the beginning of another large message is already
+    /// buffered, only to grow it again.
+    WhenEmpty,
+}
+
+/// How the reader behaves once the underlying reader reported the end of
+/// the stream, by returning 0 from a read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EofPolicy {
+    /// Try to read again the next time more data is needed. This is useful
+    /// with files that are appended to while they are being parsed.
+    Retry,
+    /// Never read from the underlying reader again: parsers needing more data
+    /// will immediately get `Error::Eof`.
+    Sticky,
+}
+
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) capacity: usize,
+    pub(crate) max_capacity: usize,
+    pub(crate) growth: GrowthPolicy,
+    pub(crate) shrink: ShrinkPolicy,
+    pub(crate) eof: EofPolicy,
+    pub(crate) track_position: bool,
+    pub(crate) pool: Option<Arc<dyn BufferPool>>,
+}
+
+impl Config {
+    /// Configuration of a fixed size buffer, as created by `with_capacity`.
+    pub(crate) fn with_capacity(capacity: usize) -> Config {
+        Config {
+            capacity,
+            max_capacity: capacity,
+            growth: GrowthPolicy::Double,
+            shrink: ShrinkPolicy::Never,
+            eof: EofPolicy::Retry,
+            track_position: false,
+            pool: None,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("capacity", &self.capacity)
+            .field("max_capacity", &self.max_capacity)
+            .field("growth", &self.growth)
+            .field("shrink", &self.shrink)
+            .field("eof", &self.eof)
+            .field("track_position", &self.track_position)
+            .field("pool", &self.pool.is_some())
+            .finish()
+    }
+}
+
+/// Creates `bufreader::BufReader`, `async_bufreader::BufReader` and
+/// `bytes_bufreader::BufReader` instances with a common configuration.
+///
+/// By default, the buffer has a fixed capacity of 8 KB, does not track the
+/// stream position and retries reading after the end of the stream, like a
+/// reader created with `BufReader::new`.
+#[derive(Clone, Debug)]
+pub struct BufReaderBuilder {
+    config: Config,
+}
+
+impl BufReaderBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> BufReaderBuilder {
+        BufReaderBuilder {
+            config: Config::with_capacity(DEFAULT_BUF_SIZE),
+        }
+    }
+
+    /// Sets the initial capacity of the buffer.
+    ///
+    /// If the maximum capacity is lower, it is raised to this value.
+    pub fn capacity(mut self, capacity: usize) -> BufReaderBuilder {
+        self.config.capacity = capacity;
+        self
+    }
+
+    /// Sets the capacity the buffer can grow to when a parser needs more
+    /// data than the buffer can hold.
+    ///
+    /// When this limit is reached, filling the buffer returns an error of
+    /// kind `Interrupted`. By default, the maximum capacity is the initial
+    /// capacity: the buffer does not grow.
+    pub fn max_capacity(mut self, max_capacity: usize) -> BufReaderBuilder {
+        self.config.max_capacity = max_capacity;
+        self
+    }
+
+    /// Sets how the buffer grows, up to the maximum capacity. Defaults to
+    /// [`GrowthPolicy::Double`].
+    pub fn growth(mut self, growth: GrowthPolicy) -> BufReaderBuilder {
+        self.config.growth = growth;
+        self
+    }
+
+    /// Sets what happens to the buffer after it grew. Defaults to
+    /// [`ShrinkPolicy::Never`].
+    pub fn shrink(mut self, shrink: ShrinkPolicy) -> BufReaderBuilder {
+        self.config.shrink = shrink;
+        self
+    }
+
+    /// Sets how the reader behaves at the end of the stream. Defaults to
+    /// [`EofPolicy::Retry`].
+    pub fn eof(mut self, eof: EofPolicy) -> BufReaderBuilder {
+        self.config.eof = eof;
+        self
+    }
+
+    /// Tracks the number of bytes consumed from the stream, available through
+    /// the reader's `position` method. Disabled by default.
+    pub fn track_position(mut self, track_position: bool) -> BufReaderBuilder {
+        self.config.track_position = track_position;
+        self
+    }
+
+    /// Takes the readers' buffers from `pool`, and gives them back when the
+    /// readers are dropped or unwrapped. See the [`pool`](crate::pool) module.
+    pub fn pool(mut self, pool: Arc<dyn BufferPool>) -> BufReaderBuilder {
+        self.config.pool = Some(pool);
+        self
+    }
+
+    fn config(&self) -> Config {
+        let mut config = self.config.clone();
+        if config.max_capacity < config.capacity {
+            config.max_capacity = config.capacity;
+        }
+        config
+    }
+
+    /// Creates a synchronous reader with this configuration.
+    pub fn build<R: Read>(&self, inner: R) -> bufreader::BufReader<R> {
+        bufreader::BufReader::with_config(self.config(), inner)
+    }
+
+    /// Creates an asynchronous reader with this configuration.
+    #[cfg(feature = "async")]
+    pub fn build_async<R: AsyncRead>(&self, inner: R) -> async_bufreader::BufReader<R> {
+        async_bufreader::BufReader::with_config(self.config(), inner)
+    }
+
+    /// Creates a reader storing its data in a `bytes::BytesMut`, with this
+    /// configuration.
+    ///
+    /// The buffer pool is not used: the memory of this reader is shared with
+    /// the `Bytes` handles returned by `parse_bytes`, so it cannot be given
+    /// back to a pool when the reader is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{bytes::streaming::take, IResult};
+    /// use nom_bufreader::builder::BufReaderBuilder;
+    /// use nom_bufreader::bytes_bufreader::ParseBytes;
+    /// use nom_bufreader::Error;
+    ///
+    /// fn payload(i: &[u8]) -> IResult<&[u8], &[u8], ()> {
+    ///     take(100usize)(i)
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let data = vec![1u8; 100];
+    ///     let mut reader = BufReaderBuilder::new()
+    ///         .capacity(16)
+    ///         .max_capacity(128)
+    ///         .track_position(true)
+    ///         .build_bytes(&data[..]);
+    ///
+    ///     let payload = reader.parse_bytes(payload)?;
+    ///     assert_eq!(payload.len(), 100);
+    ///     assert_eq!(reader.position(), Some(100));
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "bytes")]
+    pub fn build_bytes<R: Read>(&self, inner: R) -> bytes_bufreader::BufReader<R> {
+        bytes_bufreader::BufReader::with_config(self.config(), inner)
+    }
+}
+
+impl Default for BufReaderBuilder {
+    fn default() -> BufReaderBuilder {
+        BufReaderBuilder::new()
+    }
+}
@@ -0,0 +1,781 @@
+// a BufReader variant storing its data in a `bytes::BytesMut`
+//
+// the data consumed by `parse_bytes` is split off the front of the buffer, so
+// sub-slices recognized by a parser can be handed out as `Bytes` without
+// copying. Other consumed data stays in the buffer until it is refilled, like
+// in the other readers
+
+use super::bufreader::DEFAULT_BUF_SIZE;
+use super::builder::{Config, EofPolicy, ShrinkPolicy};
+use super::replay::Replay;
+use super::Error;
+use bytes::{Buf, Bytes, BytesMut};
+use nom::{Err, Offset, Parser};
+use std::cmp;
+use std::fmt;
+use std::io::{self, BufRead, IoSliceMut, Read};
+
+#[cfg(feature = "async")]
+use async_trait::async_trait;
+#[cfg(feature = "async")]
+use futures::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite};
+#[cfg(feature = "async")]
+use futures::ready;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+
+/// The `BufReader<R>` struct adds buffering to any reader, using a
+/// [`BytesMut`] as storage.
+///
+/// It works like [`bufreader::BufReader`](crate::bufreader::BufReader), and
+/// can be used with [`Parse`](crate::Parse) and (with the `async` feature)
+/// [`AsyncParse`](crate::AsyncParse), but it additionally implements
+/// [`ParseBytes`] (and [`AsyncParseBytes`]): a parser can recognize a slice
+/// of the buffer, which is then split off and returned as a [`Bytes`] handle
+/// sharing the buffer's memory. The handle can be sent to another task or
+/// thread without copying the payload.
+///
+/// While `Bytes` handles returned by `parse_bytes` are alive, the memory they
+/// point to cannot be reused, so refilling the buffer will allocate a new
+/// one.
+///
+/// Like the other readers, it can be configured with a
+/// [`BufReaderBuilder`](crate::builder::BufReaderBuilder), through
+/// [`build_bytes`](crate::builder::BufReaderBuilder::build_bytes).
+///
+/// # Examples
+///
+/// ```rust
+/// use nom::{multi::length_data, number::streaming::be_u16, IResult};
+/// use nom_bufreader::bytes_bufreader::{BufReader, ParseBytes};
+/// use nom_bufreader::Error;
+///
+/// fn frame(i: &[u8]) -> IResult<&[u8], &[u8], ()> {
+///     length_data(be_u16)(i)
+/// }
+///
+/// fn main() -> Result<(), Error<()>> {
+///     let mut reader = BufReader::new(&b"\x00\x05hello\x00\x05world"[..]);
+///
+///     let first = reader.parse_bytes(frame)?;
+///     let second = reader.parse_bytes(frame)?;
+///     assert_eq!(&first[..], b"hello");
+///     assert_eq!(&second[..], b"world");
+///     Ok(())
+/// }
+/// ```
+pub struct BufReader<R> {
+    inner: R,
+    buf: BytesMut,
+    /// start of the data that was not consumed yet
+    pos: usize,
+    /// end of the data read into `buf`
+    filled: usize,
+    capacity: usize,
+    config: Config,
+    position: Option<u64>,
+    eof: bool,
+}
+
+impl<R> BufReader<R> {
+    /// Creates a new `BufReader<R>` with a default buffer capacity. The default is currently 8 KB,
+    /// but may change in the future.
+    pub fn new(inner: R) -> BufReader<R> {
+        BufReader::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Creates a new `BufReader<R>` with the specified buffer capacity.
+    pub fn with_capacity(capacity: usize, inner: R) -> BufReader<R> {
+        BufReader::with_config(Config::with_capacity(capacity), inner)
+    }
+
+    /// Creates a new `BufReader<R>` with a default buffer capacity, holding
+    /// `data` in its buffer.
+    ///
+    /// This is useful when some data was already read from `inner` by another
+    /// component: it will be parsed before anything new is read. If `data` is
+    /// larger than the default capacity, the buffer is created large enough
+    /// to hold it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom::{bytes::streaming::take, IResult};
+    /// use nom_bufreader::bytes_bufreader::{BufReader, ParseBytes};
+    /// use nom_bufreader::Error;
+    ///
+    /// fn greeting(i: &[u8]) -> IResult<&[u8], &[u8], ()> {
+    ///     take(11usize)(i)
+    /// }
+    ///
+    /// fn main() -> Result<(), Error<()>> {
+    ///     let mut reader = BufReader::with_initial_data(&b" world"[..], b"hello".to_vec());
+    ///     let greeting = reader.parse_bytes(greeting)?;
+    ///     assert_eq!(&greeting[..], b"hello world");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_initial_data(inner: R, data: Vec<u8>) -> BufReader<R> {
+        let config = Config::with_capacity(cmp::max(DEFAULT_BUF_SIZE, data.len()));
+        let mut reader = BufReader::with_config(config, inner);
+        reader.buf.extend_from_slice(&data);
+        reader.filled = data.len();
+        reader
+    }
+
+    pub(crate) fn with_config(config: Config, inner: R) -> BufReader<R> {
+        BufReader {
+            inner,
+            buf: BytesMut::with_capacity(config.capacity),
+            pos: 0,
+            filled: 0,
+            capacity: config.capacity,
+            position: if config.track_position { Some(0) } else { None },
+            config,
+            eof: false,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns a reference to the internally buffered data.
+    ///
+    /// Unlike `fill_buf`, this will not attempt to fill the buffer if it is empty.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Returns the number of bytes the internal buffer can hold at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of bytes the internal buffer can grow to, when a
+    /// parser needs more data than it currently holds.
+    ///
+    /// This is the same as [`capacity`](BufReader::capacity), unless the
+    /// reader was created with a [`BufReaderBuilder`](crate::builder::BufReaderBuilder).
+    pub fn max_capacity(&self) -> usize {
+        self.config.max_capacity
+    }
+
+    /// Shrinks the internal buffer back to its initial capacity, or to the
+    /// size of the buffered data if it is larger.
+    ///
+    /// This is useful after a large message made the buffer grow, see
+    /// [`ShrinkPolicy`](crate::builder::ShrinkPolicy) to do it automatically.
+    pub fn shrink_to_fit(&mut self) {
+        let capacity = cmp::max(self.config.capacity, self.filled - self.pos);
+        if capacity < self.capacity {
+            self.shrink_to(capacity);
+        }
+    }
+
+    /// Returns the number of bytes consumed from the stream, if position
+    /// tracking was enabled with
+    /// [`BufReaderBuilder::track_position`](crate::builder::BufReaderBuilder::track_position).
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Unwraps this `BufReader<R>`, returning the underlying reader.
+    ///
+    /// Note that any leftover data in the internal buffer is lost. Therefore,
+    /// a following read from the underlying reader may lead to data loss.
+    /// Use [`into_parts`](BufReader::into_parts) or
+    /// [`into_inner_with_buffer`](BufReader::into_inner_with_buffer) to keep
+    /// that data.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Unwraps this `BufReader<R>`, returning the underlying reader and the
+    /// data that was buffered but not consumed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::BufRead;
+    /// use nom_bufreader::bytes_bufreader::BufReader;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///     let mut reader = BufReader::new(&b"hello world"[..]);
+    ///     reader.fill_buf()?;
+    ///     reader.consume(6);
+    ///
+    ///     let (inner, buffered) = reader.into_parts();
+    ///     assert_eq!(buffered, b"world");
+    ///     assert!(inner.is_empty());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        let buffered = self.buffer().to_vec();
+        (self.inner, buffered)
+    }
+
+    /// Unwraps this `BufReader<R>`, returning the underlying reader wrapped
+    /// in a [`Replay`] that will return the buffered data before reading
+    /// from it.
+    ///
+    /// See the [`replay`](crate::replay) module.
+    pub fn into_inner_with_buffer(self) -> Replay<R> {
+        let (inner, buffered) = self.into_parts();
+        Replay::new(buffered, inner)
+    }
+
+    /// Locates the output of a parser in the buffered data, copying it if it
+    /// is not a sub-slice of that data, like a static slice returned by
+    /// `nom::combinator::value`.
+    fn locate(&self, o: &[u8]) -> Output {
+        let data = self.buffer();
+        let base = data.as_ptr() as usize;
+        let start = o.as_ptr() as usize;
+        if start >= base && start + o.len() <= base + data.len() {
+            Output::Range(start - base, start - base + o.len())
+        } else {
+            Output::Copied(Bytes::copy_from_slice(o))
+        }
+    }
+
+    /// Consumes `consumed` bytes, and returns the parser's output as a
+    /// `Bytes` handle, splitting it off the buffer if possible.
+    fn split_consumed(&mut self, consumed: usize, output: Output) -> Bytes {
+        let bytes = match output {
+            Output::Range(start, end) if end <= consumed => {
+                let pos = self.pos;
+                let bytes = self
+                    .buf
+                    .split_to(pos + consumed)
+                    .freeze()
+                    .slice(pos + start..pos + end);
+                self.filled -= pos + consumed;
+                self.pos = 0;
+                self.advance_position(consumed);
+                self.apply_shrink_policy();
+                return bytes;
+            }
+            // the parser returned a slice extending past the data it consumed,
+            // we cannot split it off without losing unconsumed data
+            Output::Range(start, end) => Bytes::copy_from_slice(&self.buffer()[start..end]),
+            Output::Copied(bytes) => bytes,
+        };
+
+        self.consume_buffered(consumed);
+        bytes
+    }
+
+    fn consume_buffered(&mut self, amt: usize) {
+        let amt = cmp::min(amt, self.filled - self.pos);
+        self.pos += amt;
+        self.advance_position(amt);
+        self.apply_shrink_policy();
+    }
+
+    fn advance_position(&mut self, amt: usize) {
+        if let Some(position) = self.position.as_mut() {
+            *position += amt as u64;
+        }
+    }
+
+    /// Makes sure there is room for more data in the buffer, by moving the
+    /// buffered data to its beginning or growing it, and returns the offset
+    /// where new data can be read.
+    fn prepare_fill(&mut self) -> io::Result<usize> {
+        if self.pos == self.filled {
+            self.pos = 0;
+            self.filled = 0;
+        }
+
+        if self.filled == self.capacity {
+            if self.pos > 0 {
+                self.buf.copy_within(self.pos..self.filled, 0);
+                self.filled -= self.pos;
+                self.pos = 0;
+            } else if self.capacity < self.config.max_capacity {
+                let capacity = cmp::max(self.config.growth.grow(self.capacity), self.capacity + 1);
+                self.capacity = cmp::min(capacity, self.config.max_capacity);
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "buffer completely filled",
+                ));
+            }
+        }
+
+        if self.buf.capacity() < self.capacity {
+            // the buffer grew, or a `Bytes` handle was split off its front:
+            // this reclaims the memory of that handle if it was dropped,
+            // otherwise it allocates a new buffer. Only the buffered data is
+            // kept, so the rest of the buffer must be initialized again
+            self.buf.truncate(self.filled);
+            self.buf.advance(self.pos);
+            self.filled -= self.pos;
+            self.pos = 0;
+            self.buf.reserve(self.capacity - self.filled);
+        }
+
+        // the bytes after `filled` that were initialized by previous fills
+        // are kept in the buffer, so they are only zeroed once
+        let initialized = cmp::min(self.capacity, self.filled + DEFAULT_BUF_SIZE);
+        if self.buf.len() < initialized {
+            self.buf.resize(initialized, 0);
+        }
+        Ok(self.filled)
+    }
+
+    /// Marks `amt` bytes as read after the offset returned by `prepare_fill`.
+    fn filled(&mut self, amt: usize) {
+        self.filled += amt;
+        if amt == 0 && self.config.eof == EofPolicy::Sticky {
+            self.eof = true;
+        }
+    }
+
+    /// Shrinks the buffer back to its initial capacity if it grew, and the
+    /// shrink policy allows it.
+    fn apply_shrink_policy(&mut self) {
+        if self.capacity <= self.config.capacity {
+            return;
+        }
+
+        let len = self.filled - self.pos;
+        let shrink = match self.config.shrink {
+            ShrinkPolicy::Never => false,
+            ShrinkPolicy::ToInitial => len <= self.config.capacity,
+            ShrinkPolicy::WhenEmpty => len == 0,
+        };
+
+        if shrink {
+            self.shrink_to(self.config.capacity);
+        }
+    }
+
+    /// Replaces the buffer with a smaller one holding the buffered data.
+    fn shrink_to(&mut self, capacity: usize) {
+        let mut buf = BytesMut::with_capacity(capacity);
+        buf.extend_from_slice(self.buffer());
+        self.filled = buf.len();
+        self.pos = 0;
+        self.buf = buf;
+        self.capacity = capacity;
+    }
+}
+
+impl<R: Read> Read for BufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // If we don't have any buffered data and we're doing a massive read
+        // (larger than our internal buffer), bypass our internal buffer
+        // entirely.
+        if self.pos == self.filled && buf.len() >= self.capacity {
+            let read = self.inner.read(buf)?;
+            self.advance_position(read);
+            return Ok(read);
+        }
+        let nread = {
+            let mut rem = self.fill_buf()?;
+            rem.read(buf)?
+        };
+        self.consume(nread);
+        Ok(nread)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        let total_len = bufs.iter().map(|b| b.len()).sum::<usize>();
+        if self.pos == self.filled && total_len >= self.capacity {
+            let read = self.inner.read_vectored(bufs)?;
+            self.advance_position(read);
+            return Ok(read);
+        }
+        let nread = {
+            let mut rem = self.fill_buf()?;
+            rem.read_vectored(bufs)?
+        };
+        self.consume(nread);
+        Ok(nread)
+    }
+}
+
+impl<R: Read> BufRead for BufReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if !self.eof {
+            let len = self.prepare_fill()?;
+            let read = self.inner.read(&mut self.buf[len..])?;
+            self.filled(read);
+        }
+        Ok(self.buffer())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consume_buffered(amt);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncRead for BufReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // If we don't have any buffered data and we're doing a massive read
+        // (larger than our internal buffer), bypass our internal buffer
+        // entirely.
+        if self.pos == self.filled && buf.len() >= self.capacity {
+            let read = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+            self.advance_position(read);
+            return Poll::Ready(Ok(read));
+        }
+        let mut rem = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let nread = rem.read(buf)?;
+        self.consume(nread);
+        Poll::Ready(Ok(nread))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncBufRead for BufReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+
+        if !this.eof {
+            let len = this.prepare_fill()?;
+            let read = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut this.buf[len..]))?;
+            this.filled(read);
+        }
+
+        Poll::Ready(Ok(this.buffer()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().consume_buffered(amt);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncWrite + Unpin> AsyncWrite for BufReader<R> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+impl<R> fmt::Debug for BufReader<R>
+where
+    R: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("BufReader")
+            .field("reader", &self.inner)
+            .field(
+                "buffer",
+                &format_args!("{}/{}", self.filled - self.pos, self.capacity),
+            )
+            .finish()
+    }
+}
+
+/// Where the output of a parser was found.
+enum Output {
+    /// A `start..end` range of the buffer
+    Range(usize, usize),
+    /// The output was not in the buffer
+    Copied(Bytes),
+}
+
+/// Parses a slice out of the buffer and returns it as a [`Bytes`] handle,
+/// without copying.
+///
+/// The parser should return a sub-slice of its input, usually a part of what
+/// it consumed (like `nom::bytes::streaming::take(len)` does). If the returned
+/// slice extends past the consumed data, or is not part of the input, it is
+/// copied instead.
+pub trait ParseBytes<E, P> {
+    fn parse_bytes(&mut self, p: P) -> Result<Bytes, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], &'a [u8], E>;
+}
+
+impl<R: Read, E, P> ParseBytes<E, P> for BufReader<R> {
+    fn parse_bytes(&mut self, mut p: P) -> Result<Bytes, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], &'a [u8], E>,
+    {
+        let mut eof = false;
+        let mut error = None;
+        loop {
+            let opt = match p.parse(self.buffer()) {
+                Err(Err::Error(e)) => return Err(Error::Error(e)),
+                Err(Err::Failure(e)) => return Err(Error::Failure(e)),
+                Err(Err::Incomplete(_)) => None,
+                Ok((i, o)) => {
+                    let consumed = self.buffer().offset(i);
+                    Some((consumed, self.locate(o)))
+                }
+            };
+
+            match opt {
+                Some((consumed, output)) => {
+                    return Ok(self.split_consumed(consumed, output));
+                }
+                None => {
+                    if eof {
+                        return Err(Error::Eof);
+                    }
+
+                    if let Some(e) = error.take() {
+                        return Err(Error::Io(e));
+                    }
+
+                    let len = self.buffer().len();
+                    match self.fill_buf() {
+                        Err(e) => error = Some(e),
+                        Ok(s) => {
+                            if s.len() == len {
+                                eof = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Asynchronous version of [`ParseBytes`].
+#[cfg(feature = "async")]
+#[async_trait]
+pub trait AsyncParseBytes<E, P> {
+    async fn parse_bytes(&mut self, p: P) -> Result<Bytes, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], &'a [u8], E> + Send + 'async_trait;
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send, E, P> AsyncParseBytes<E, P> for BufReader<R> {
+    async fn parse_bytes(&mut self, mut p: P) -> Result<Bytes, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], &'a [u8], E> + Send + 'async_trait,
+    {
+        let mut eof = false;
+        loop {
+            let opt = match p.parse(self.buffer()) {
+                Err(Err::Error(e)) => return Err(Error::Error(e)),
+                Err(Err::Failure(e)) => return Err(Error::Failure(e)),
+                Err(Err::Incomplete(_)) => None,
+                Ok((i, o)) => {
+                    let consumed = self.buffer().offset(i);
+                    Some((consumed, self.locate(o)))
+                }
+            };
+
+            match opt {
+                Some((consumed, output)) => {
+                    return Ok(self.split_consumed(consumed, output));
+                }
+                None => {
+                    if eof {
+                        return Err(Error::Eof);
+                    }
+
+                    let len = self.buffer().len();
+                    if self.fill_buf().await?.len() == len {
+                        eof = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufReader, ParseBytes};
+    use crate::builder::{BufReaderBuilder, EofPolicy, ShrinkPolicy};
+    use crate::Error;
+    use nom::bytes::streaming::take;
+    use nom::IResult;
+    use std::collections::VecDeque;
+    use std::io::{self, BufRead, Read};
+
+    /// Returns one chunk per read.
+    struct Chunks(VecDeque<&'static [u8]>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(chunk.len())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    fn chunks(chunks: &[&'static [u8]]) -> Chunks {
+        Chunks(chunks.iter().copied().collect())
+    }
+
+    fn take4(i: &[u8]) -> IResult<&[u8], &[u8], ()> {
+        take(4usize)(i)
+    }
+
+    fn take20(i: &[u8]) -> IResult<&[u8], &[u8], ()> {
+        take(20usize)(i)
+    }
+
+    #[test]
+    fn refill_does_not_zero_initialized_space_again() {
+        let mut reader = BufReader::with_capacity(16, chunks(&[b"abcdefgh", b"XY"]));
+        assert_eq!(reader.fill_buf().unwrap(), b"abcdefgh");
+        assert_eq!(reader.buf.len(), 16);
+        reader.consume(8);
+
+        assert_eq!(reader.fill_buf().unwrap(), b"XY");
+        // the rest of the first read is still there
+        assert_eq!(&reader.buf[2..8], b"cdefgh");
+        assert_eq!(reader.buf.len(), 16);
+    }
+
+    #[test]
+    fn refill_moves_data_to_the_beginning() {
+        let mut reader = BufReader::with_capacity(8, chunks(&[b"abcdefgh", b"ijkl"]));
+        reader.fill_buf().unwrap();
+        reader.consume(6);
+
+        assert_eq!(reader.fill_buf().unwrap(), b"ghijkl");
+        assert_eq!(reader.capacity(), 8);
+    }
+
+    #[test]
+    fn parse_bytes_across_refills() {
+        let mut reader = BufReader::with_capacity(6, chunks(&[b"aaaab", b"bbbc", b"ccc"]));
+        let first = reader.parse_bytes(take4).unwrap();
+        let second = reader.parse_bytes(take4).unwrap();
+        let third = reader.parse_bytes(take4).unwrap();
+
+        assert_eq!(&first[..], b"aaaa");
+        assert_eq!(&second[..], b"bbbb");
+        assert_eq!(&third[..], b"cccc");
+        assert!(matches!(reader.parse_bytes(take4), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn parse_bytes_after_consume() {
+        let mut reader = BufReader::new(&b"xxabcd"[..]);
+        reader.fill_buf().unwrap();
+        reader.consume(2);
+
+        assert_eq!(&reader.parse_bytes(take4).unwrap()[..], b"abcd");
+        assert!(reader.buffer().is_empty());
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_frame() {
+        let mut reader = BufReader::new(&b"abc"[..]);
+        assert!(matches!(reader.parse_bytes(take4), Err(Error::Eof)));
+        assert_eq!(reader.buffer(), b"abc");
+    }
+
+    #[test]
+    fn frame_larger_than_the_buffer() {
+        let data = [0u8; 32];
+        let mut reader = BufReader::with_capacity(16, &data[..]);
+        match reader.parse_bytes(take20) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::Interrupted),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn grows_up_to_max_capacity() {
+        let data = [0u8; 32];
+        let builder = BufReaderBuilder::new().capacity(8).max_capacity(20);
+
+        let mut reader = builder.build_bytes(&data[..]);
+        assert_eq!(reader.parse_bytes(take20).unwrap().len(), 20);
+        assert_eq!(reader.capacity(), 20);
+
+        let mut reader = builder.max_capacity(19).build_bytes(&data[..]);
+        assert!(matches!(reader.parse_bytes(take20), Err(Error::Io(_))));
+        assert_eq!(reader.buffer().len(), 19);
+    }
+
+    #[test]
+    fn shrinks_when_empty() {
+        let data = [0u8; 20];
+        let mut reader = BufReaderBuilder::new()
+            .capacity(8)
+            .max_capacity(32)
+            .shrink(ShrinkPolicy::WhenEmpty)
+            .build_bytes(&data[..]);
+
+        reader.parse_bytes(take20).unwrap();
+        assert_eq!(reader.capacity(), 8);
+    }
+
+    #[test]
+    fn sticky_eof() {
+        let mut reader = BufReaderBuilder::new()
+            .eof(EofPolicy::Sticky)
+            .build_bytes(chunks(&[b"ab", b"", b"cd"]));
+
+        assert!(matches!(reader.parse_bytes(take4), Err(Error::Eof)));
+        // the empty read is not retried
+        assert_eq!(reader.fill_buf().unwrap(), b"ab");
+    }
+
+    #[test]
+    fn tracks_position() {
+        let mut reader = BufReaderBuilder::new()
+            .track_position(true)
+            .build_bytes(&b"abcdefgh"[..]);
+
+        reader.parse_bytes(take4).unwrap();
+        assert_eq!(reader.position(), Some(4));
+        reader.consume(2);
+        assert_eq!(reader.position(), Some(6));
+    }
+
+    #[test]
+    fn initial_data_and_into_parts() {
+        let mut reader = BufReader::with_initial_data(&b"cdef"[..], b"ab".to_vec());
+        assert_eq!(&reader.parse_bytes(take4).unwrap()[..], b"abcd");
+
+        let (inner, buffered) = reader.into_parts();
+        assert_eq!(buffered, b"ef");
+        assert!(inner.is_empty());
+    }
+}
@@ -0,0 +1,573 @@
+//! HTTP chunked transfer coding
+//!
+//! Once a head with `Transfer-Encoding: chunked` was parsed, the body is sent
+//! as a series of chunks, each preceded by its size, and followed by
+//! optional trailer fields. [`ChunkedReader`] wraps the reader that parsed
+//! the head, and returns the decoded body through `Read`, parsing chunk size
+//! lines and trailers as they come. At the end of the body, the reader can
+//! be unwrapped with [`ChunkedReader::into_inner`] to parse the next message.
+//! [`AsyncChunkedReader`] does the same for `async_bufreader::BufReader`,
+//! implementing `AsyncRead`.
+//!
+//! Chunk size lines are limited to [`MAX_CHUNK_SIZE_LINE_LEN`] bytes,
+//! including chunk extensions, and the trailer section to
+//! [`MAX_TRAILERS_SIZE`] bytes. A line or trailer section that does not fit
+//! in the reader's buffer is rejected too, even if it is under those limits.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::chunked::ChunkedReader;
+//! use std::io::Read;
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let reader = BufReader::new(
+//!         &b"5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nExpires: never\r\n\r\nnext"[..],
+//!     );
+//!
+//!     let mut chunked = ChunkedReader::new(reader);
+//!     let mut body = String::new();
+//!     chunked.read_to_string(&mut body)?;
+//!     assert_eq!(body, "hello, world");
+//!     assert_eq!(chunked.trailers().unwrap()["expires"], "never");
+//!
+//!     let reader = chunked.into_inner();
+//!     assert_eq!(reader.buffer(), b"next");
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Oversized chunk extensions are rejected as invalid data:
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::chunked::ChunkedReader;
+//! use std::io::{ErrorKind, Read};
+//!
+//! let mut data = b"5;ext=".to_vec();
+//! data.extend_from_slice(&[b'a'; 100]);
+//! data.extend_from_slice(b"\r\nhello\r\n0\r\n\r\n");
+//!
+//! // the line does not fit in the buffer
+//! let mut chunked = ChunkedReader::new(BufReader::with_capacity(32, &data[..]));
+//! let err = chunked.read_to_end(&mut Vec::new()).unwrap_err();
+//! assert_eq!(err.kind(), ErrorKind::InvalidData);
+//!
+//! // the line is longer than `MAX_CHUNK_SIZE_LINE_LEN`
+//! data.splice(6..6, vec![b'a'; 2000]);
+//! let mut chunked = ChunkedReader::new(BufReader::new(&data[..]));
+//! let err = chunked.read_to_end(&mut Vec::new()).unwrap_err();
+//! assert_eq!(err.kind(), ErrorKind::InvalidData);
+//! ```
+use super::bufreader::BufReader;
+use super::http::{crlf, headers, HttpError, DEFAULT_MAX_HEAD_SIZE};
+use super::{Error, Parse};
+use ::http::HeaderMap;
+use nom::bytes::streaming::{take_till, take_while1};
+use nom::{Err, IResult, Offset};
+use std::fmt;
+use std::io::{self, Read};
+
+#[cfg(feature = "async")]
+use super::async_bufreader;
+#[cfg(feature = "async")]
+use futures::io::{AsyncBufRead, AsyncRead};
+#[cfg(feature = "async")]
+use futures::ready;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+
+/// Maximum length of a chunk size line, including chunk extensions and the
+/// line ending.
+pub const MAX_CHUNK_SIZE_LINE_LEN: usize = 1024;
+
+/// Maximum size of the trailer section, including the final empty line.
+pub const MAX_TRAILERS_SIZE: usize = DEFAULT_MAX_HEAD_SIZE;
+
+/// Fails with `error` if the data parsed from `i` is longer than `max_len`,
+/// or if it is incomplete and already that long.
+fn limit<'a, O>(
+    i: &'a [u8],
+    max_len: usize,
+    error: HttpError,
+    res: IResult<&'a [u8], O, HttpError>,
+) -> IResult<&'a [u8], O, HttpError> {
+    match res {
+        Err(Err::Incomplete(_)) if i.len() >= max_len => Err(Err::Failure(error)),
+        Ok((rest, _)) if i.offset(rest) > max_len => Err(Err::Failure(error)),
+        res => res,
+    }
+}
+
+/// Parses a chunk size line, like `1a;name=value\r\n`, ignoring chunk
+/// extensions.
+///
+/// Lines longer than [`MAX_CHUNK_SIZE_LINE_LEN`] are rejected with
+/// `HttpError::ChunkSizeLineTooLarge`.
+pub fn chunk_size(i: &[u8]) -> IResult<&[u8], u64, HttpError> {
+    let res = chunk_size_line(i);
+    limit(
+        i,
+        MAX_CHUNK_SIZE_LINE_LEN,
+        HttpError::ChunkSizeLineTooLarge,
+        res,
+    )
+}
+
+fn chunk_size_line(i: &[u8]) -> IResult<&[u8], u64, HttpError> {
+    let (i, digits) = take_while1(|c: u8| c.is_ascii_hexdigit())(i)?;
+    let (i, _) = take_till(|c| c == b'\r' || c == b'\n')(i)?;
+    let (i, _) = crlf(i)?;
+
+    let size = digits
+        .iter()
+        .try_fold(0u64, |size, digit| {
+            let value = (*digit as char).to_digit(16)? as u64;
+            size.checked_mul(16)?.checked_add(value)
+        })
+        .ok_or(Err::Failure(HttpError::InvalidChunkSize))?;
+    Ok((i, size))
+}
+
+/// Parses the trailer section, up to [`MAX_TRAILERS_SIZE`] bytes.
+fn trailers(i: &[u8]) -> IResult<&[u8], HeaderMap, HttpError> {
+    let res = headers(i);
+    limit(i, MAX_TRAILERS_SIZE, HttpError::TrailersTooLarge, res)
+}
+
+/// Where the reader is in the body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Expecting a chunk size line
+    Size,
+    /// Reading chunk data, with the number of bytes left in the chunk
+    Data(u64),
+    /// Expecting the line ending after chunk data
+    DataEnd,
+    /// Expecting the trailer section
+    Trailers,
+    /// The whole body was read
+    Done,
+}
+
+fn into_io_error(e: Error<HttpError>) -> io::Error {
+    match e {
+        Error::Io(e) => e,
+        Error::Eof => io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "unexpected end of chunked body",
+        ),
+        Error::Error(e) | Error::Failure(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+        Error::TooLarge => io::Error::new(
+            io::ErrorKind::InvalidData,
+            "chunked coding line does not fit in the buffer",
+        ),
+    }
+}
+
+/// Converts the result of `Parse::parse`. When the parser needs more data
+/// than the reader's buffer can hold, filling it fails with an error of kind
+/// `Interrupted`, that must not be returned as is: `Read::read_to_end`
+/// would retry forever.
+fn parse_result<O, R>(reader: &BufReader<R>, res: Result<O, Error<HttpError>>) -> io::Result<O> {
+    match res {
+        Err(Error::Io(e))
+            if e.kind() == io::ErrorKind::Interrupted
+                && reader.buffer().len() == reader.max_capacity() =>
+        {
+            Err(into_io_error(Error::TooLarge))
+        }
+        res => res.map_err(into_io_error),
+    }
+}
+
+fn unexpected_eof() -> io::Error {
+    into_io_error(Error::Eof)
+}
+
+/// Reader decoding a chunked body from a [`BufReader`].
+///
+/// Errors in the chunked coding, including lines over the limits or larger
+/// than the reader's buffer, are returned as IO errors of kind
+/// `InvalidData`, and the end of the stream before the end of the body as
+/// `UnexpectedEof`.
+pub struct ChunkedReader<R> {
+    reader: BufReader<R>,
+    state: State,
+    trailers: Option<HeaderMap>,
+}
+
+impl<R: Read> ChunkedReader<R> {
+    /// Creates a reader decoding the chunked body at the current position of
+    /// `reader`.
+    pub fn new(reader: BufReader<R>) -> ChunkedReader<R> {
+        ChunkedReader {
+            reader,
+            state: State::Size,
+            trailers: None,
+        }
+    }
+}
+
+impl<R> ChunkedReader<R> {
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &BufReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut BufReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns `true` once the whole body, including trailers, was read.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Returns the trailer fields, once the whole body was read.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Unwraps this `ChunkedReader<R>`, returning the underlying reader.
+    ///
+    /// Once the whole body was read, it is positioned after the trailers.
+    /// Otherwise, it is left in the middle of the body.
+    pub fn into_inner(self) -> BufReader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                State::Size => {
+                    let res = self.reader.parse(chunk_size);
+                    let size = parse_result(&self.reader, res)?;
+                    self.state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::Data(size)
+                    };
+                }
+                State::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+
+                    let n = self.reader.take_reader(remaining).read(buf)?;
+                    if n == 0 {
+                        return Err(unexpected_eof());
+                    }
+                    let remaining = remaining - n as u64;
+                    self.state = if remaining == 0 {
+                        State::DataEnd
+                    } else {
+                        State::Data(remaining)
+                    };
+                    return Ok(n);
+                }
+                State::DataEnd => {
+                    let res = self.reader.parse(crlf);
+                    parse_result(&self.reader, res)?;
+                    self.state = State::Size;
+                }
+                State::Trailers => {
+                    let res = self.reader.parse(trailers);
+                    let trailers = parse_result(&self.reader, res)?;
+                    self.trailers = Some(trailers);
+                    self.state = State::Done;
+                }
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for ChunkedReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkedReader")
+            .field("reader", &self.reader)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// Applies `parser` to the buffered data, refilling the buffer until it has
+/// enough data.
+#[cfg(feature = "async")]
+fn poll_parse<R, O, P>(
+    mut reader: Pin<&mut async_bufreader::BufReader<R>>,
+    cx: &mut Context<'_>,
+    parser: P,
+) -> Poll<io::Result<O>>
+where
+    R: AsyncRead,
+    P: Fn(&[u8]) -> IResult<&[u8], O, HttpError>,
+{
+    loop {
+        let res = match parser(reader.buffer()) {
+            Err(Err::Error(e)) => Err(Error::Error(e)),
+            Err(Err::Failure(e)) => Err(Error::Failure(e)),
+            Err(Err::Incomplete(_)) => Ok(None),
+            Ok((i, o)) => {
+                let offset = reader.buffer().offset(i);
+                Ok(Some((offset, o)))
+            }
+        };
+
+        match res.map_err(into_io_error)? {
+            Some((offset, o)) => {
+                reader.as_mut().consume(offset);
+                return Poll::Ready(Ok(o));
+            }
+            None => {
+                let len = reader.buffer().len();
+                if len == reader.max_capacity() {
+                    return Poll::Ready(Err(into_io_error(Error::TooLarge)));
+                }
+                if ready!(reader.as_mut().poll_fill_buf(cx))?.len() == len {
+                    return Poll::Ready(Err(unexpected_eof()));
+                }
+            }
+        }
+    }
+}
+
+/// Reader decoding a chunked body from an [`async_bufreader::BufReader`].
+///
+/// This is the asynchronous version of [`ChunkedReader`].
+#[cfg(feature = "async")]
+pub struct AsyncChunkedReader<R> {
+    reader: async_bufreader::BufReader<R>,
+    state: State,
+    trailers: Option<HeaderMap>,
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncChunkedReader<R> {
+    /// Creates a reader decoding the chunked body at the current position of
+    /// `reader`.
+    pub fn new(reader: async_bufreader::BufReader<R>) -> AsyncChunkedReader<R> {
+        AsyncChunkedReader {
+            reader,
+            state: State::Size,
+            trailers: None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R> AsyncChunkedReader<R> {
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &async_bufreader::BufReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut async_bufreader::BufReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns `true` once the whole body, including trailers, was read.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Returns the trailer fields, once the whole body was read.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Unwraps this `AsyncChunkedReader<R>`, returning the underlying reader.
+    ///
+    /// Once the whole body was read, it is positioned after the trailers.
+    /// Otherwise, it is left in the middle of the body.
+    pub fn into_inner(self) -> async_bufreader::BufReader<R> {
+        self.reader
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncChunkedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let reader = Pin::new(&mut this.reader);
+            match this.state {
+                State::Size => {
+                    let size = ready!(poll_parse(reader, cx, chunk_size))?;
+                    this.state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::Data(size)
+                    };
+                }
+                State::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Poll::Ready(Ok(0));
+                    }
+
+                    let mut take = this.reader.take_reader(remaining);
+                    let n = ready!(Pin::new(&mut take).poll_read(cx, buf))?;
+                    if n == 0 {
+                        return Poll::Ready(Err(unexpected_eof()));
+                    }
+                    let remaining = remaining - n as u64;
+                    this.state = if remaining == 0 {
+                        State::DataEnd
+                    } else {
+                        State::Data(remaining)
+                    };
+                    return Poll::Ready(Ok(n));
+                }
+                State::DataEnd => {
+                    ready!(poll_parse(reader, cx, crlf))?;
+                    this.state = State::Size;
+                }
+                State::Trailers => {
+                    let trailers = ready!(poll_parse(reader, cx, trailers))?;
+                    this.trailers = Some(trailers);
+                    this.state = State::Done;
+                }
+                State::Done => return Poll::Ready(Ok(0)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: fmt::Debug> fmt::Debug for AsyncChunkedReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncChunkedReader")
+            .field("reader", &self.reader)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_body(data: &[u8], capacity: usize) -> io::Result<Vec<u8>> {
+        let mut chunked = ChunkedReader::new(BufReader::with_capacity(capacity, data));
+        let mut body = Vec::new();
+        chunked.read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    #[cfg(feature = "async")]
+    fn read_body_async(data: &[u8], capacity: usize) -> io::Result<Vec<u8>> {
+        use futures::io::AsyncReadExt;
+
+        let reader = async_bufreader::BufReader::with_capacity(capacity, data);
+        let mut chunked = AsyncChunkedReader::new(reader);
+        let mut body = Vec::new();
+        futures::executor::block_on(chunked.read_to_end(&mut body))?;
+        Ok(body)
+    }
+
+    /// A chunked body with a chunk extension of `len` bytes.
+    fn with_extension(len: usize) -> Vec<u8> {
+        let mut data = b"5;".to_vec();
+        data.extend_from_slice(&vec![b'a'; len]);
+        data.extend_from_slice(b"\r\nhello\r\n0\r\n\r\n");
+        data
+    }
+
+    fn assert_invalid_data(res: io::Result<Vec<u8>>) {
+        match res {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", e),
+            Ok(body) => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn chunk_size_line_at_the_limit() {
+        // size, `;`, extension and CRLF
+        let data = with_extension(MAX_CHUNK_SIZE_LINE_LEN - 4);
+        assert_eq!(read_body(&data, 8192).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn chunk_size_line_over_the_limit() {
+        let data = with_extension(MAX_CHUNK_SIZE_LINE_LEN - 3);
+        assert_eq!(
+            chunk_size(&data),
+            Err(Err::Failure(HttpError::ChunkSizeLineTooLarge))
+        );
+        assert_invalid_data(read_body(&data, 8192));
+    }
+
+    #[test]
+    fn incomplete_chunk_size_line_over_the_limit() {
+        let data = vec![b'0'; MAX_CHUNK_SIZE_LINE_LEN];
+        assert_eq!(
+            chunk_size(&data),
+            Err(Err::Failure(HttpError::ChunkSizeLineTooLarge))
+        );
+        assert!(matches!(
+            chunk_size(&data[..MAX_CHUNK_SIZE_LINE_LEN - 1]),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn chunk_size_line_larger_than_the_buffer() {
+        let data = with_extension(100);
+        assert_invalid_data(read_body(&data, 32));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn chunk_size_line_larger_than_the_buffer_async() {
+        let data = with_extension(100);
+        assert_invalid_data(read_body_async(&data, 32));
+        assert_invalid_data(read_body_async(&with_extension(2000), 8192));
+    }
+
+    #[test]
+    fn trailers_over_the_limit() {
+        let mut data = b"0\r\nX-Padding: ".to_vec();
+        data.extend_from_slice(&vec![b'a'; MAX_TRAILERS_SIZE]);
+        data.extend_from_slice(b"\r\n\r\n");
+
+        assert_invalid_data(read_body(&data, 2 * MAX_TRAILERS_SIZE));
+        assert_invalid_data(read_body(&data, 1024));
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_chunk() {
+        match read_body(b"a\r\nhello", 8192) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            Ok(body) => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn eof_in_the_chunk_size_line() {
+        match read_body(b"5\r\nhello\r\n0", 8192) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            Ok(body) => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn chunk_size_overflow() {
+        assert_invalid_data(read_body(b"10000000000000000\r\n", 8192));
+    }
+}
@@ -0,0 +1,204 @@
+//! CSV records, as described in [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180)
+//!
+//! Fields are separated by commas, and records end with `\r\n` or `\n`.
+//! Fields can be enclosed in double quotes, to contain commas, line endings,
+//! or double quotes written twice. A quoted field can span any number of
+//! lines, so records cannot be split on line endings before parsing.
+//!
+//! [`record`] and [`record_owned`] are streaming parsers, returning
+//! `Incomplete` until the whole record is available, including when the
+//! input ends right after a double quote, which could be the end of the
+//! field or the first half of an escaped quote. [`record_owned`] returns
+//! fields that do not borrow the input, as needed by `Parse::parse`.
+//!
+//! The last record of a file does not need a line ending. Once the reader
+//! returned `Error::Eof`, it is left in the buffer, and can be parsed with
+//! [`last_record`] or [`last_record_owned`], which treat the end of the
+//! input as the end of the record.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::builder::{BufReaderBuilder, GrowthPolicy};
+//! use nom_bufreader::csv::{last_record_owned, record_owned, CsvError};
+//! use nom_bufreader::{Error, Parse};
+//!
+//! fn main() -> Result<(), Error<CsvError>> {
+//!     // a tiny buffer, so that the quoted field spans several refills
+//!     let mut reader = BufReaderBuilder::new()
+//!         .capacity(4)
+//!         .max_capacity(1024)
+//!         .growth(GrowthPolicy::Double)
+//!         .build(&b"name,quote\r\nada,\"I said \"\"hi\"\",\nthen left\"\r\nbob,"[..]);
+//!
+//!     assert_eq!(reader.parse(record_owned)?, vec![b"name".to_vec(), b"quote".to_vec()]);
+//!     assert_eq!(
+//!         reader.parse(record_owned)?,
+//!         vec![b"ada".to_vec(), b"I said \"hi\",\nthen left".to_vec()]
+//!     );
+//!
+//!     // the last record has no line ending
+//!     assert!(matches!(reader.parse(record_owned), Err(Error::Eof)));
+//!     assert_eq!(reader.parse(last_record_owned)?, vec![b"bob".to_vec(), b"".to_vec()]);
+//!     Ok(())
+//! }
+//! ```
+use memchr::memchr;
+use nom::error::{ErrorKind, ParseError};
+use nom::{Err, IResult, Needed};
+use std::borrow::Cow;
+use std::fmt;
+
+/// Errors returned by the CSV parsers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CsvError {
+    /// The input does not follow the CSV grammar
+    Syntax(ErrorKind),
+    /// A double quote in an unquoted field, or data after the closing quote
+    /// of a quoted field
+    InvalidQuote,
+    /// A carriage return that is not followed by a line feed
+    InvalidLineEnding,
+    /// The input ended inside a quoted field
+    UnterminatedQuote,
+}
+
+impl<I> ParseError<I> for CsvError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        CsvError::Syntax(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Syntax(kind) => write!(f, "invalid CSV syntax ({:?})", kind),
+            CsvError::InvalidQuote => f.write_str("unexpected double quote"),
+            CsvError::InvalidLineEnding => f.write_str("carriage return without line feed"),
+            CsvError::UnterminatedQuote => f.write_str("unterminated quoted field"),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Returns `Incomplete` for streaming parsers, or `value` at the end of the
+/// input.
+fn at_end<O>(eof: bool, value: impl FnOnce() -> O) -> Result<O, Err<CsvError>> {
+    if eof {
+        Ok(value())
+    } else {
+        Err(Err::Incomplete(Needed::Unknown))
+    }
+}
+
+/// Parses the content of a quoted field, after the opening quote, and its
+/// closing quote. The field is only copied if it contains escaped quotes.
+fn quoted(i: &[u8], eof: bool) -> IResult<&[u8], Cow<'_, [u8]>, CsvError> {
+    let mut unescaped: Option<Vec<u8>> = None;
+    let mut start = 0;
+    let end = loop {
+        let quote = match memchr(b'"', &i[start..]) {
+            Some(index) => start + index,
+            None if eof => return Err(Err::Failure(CsvError::UnterminatedQuote)),
+            None => return Err(Err::Incomplete(Needed::Unknown)),
+        };
+
+        match i.get(quote + 1) {
+            Some(b'"') => {
+                // keep one of the two quotes
+                unescaped
+                    .get_or_insert_with(Vec::new)
+                    .extend_from_slice(&i[start..=quote]);
+                start = quote + 2;
+            }
+            Some(_) => break quote,
+            None => break at_end(eof, || quote)?,
+        }
+    };
+
+    let field = match unescaped {
+        None => Cow::Borrowed(&i[..end]),
+        Some(mut field) => {
+            field.extend_from_slice(&i[start..end]);
+            Cow::Owned(field)
+        }
+    };
+    Ok((&i[end + 1..], field))
+}
+
+/// Parses an unquoted field, up to the next separator or line ending.
+fn unquoted(i: &[u8], eof: bool) -> IResult<&[u8], Cow<'_, [u8]>, CsvError> {
+    let end = match i
+        .iter()
+        .position(|c| matches!(c, b',' | b'\r' | b'\n' | b'"'))
+    {
+        Some(end) if i[end] == b'"' => return Err(Err::Failure(CsvError::InvalidQuote)),
+        Some(end) => end,
+        None => at_end(eof, || i.len())?,
+    };
+    Ok((&i[end..], Cow::Borrowed(&i[..end])))
+}
+
+fn fields(mut i: &[u8], eof: bool) -> IResult<&[u8], Vec<Cow<'_, [u8]>>, CsvError> {
+    if eof && i.is_empty() {
+        return Err(Err::Error(CsvError::Syntax(ErrorKind::Eof)));
+    }
+
+    let mut fields = Vec::new();
+    loop {
+        let (rest, field) = match i.first() {
+            Some(b'"') => quoted(&i[1..], eof)?,
+            _ => unquoted(i, eof)?,
+        };
+        fields.push(field);
+
+        match rest.first() {
+            Some(b',') => i = &rest[1..],
+            Some(b'\n') => return Ok((&rest[1..], fields)),
+            Some(b'\r') => {
+                return match rest.get(1) {
+                    Some(b'\n') => Ok((&rest[2..], fields)),
+                    Some(_) => Err(Err::Failure(CsvError::InvalidLineEnding)),
+                    None if eof => Err(Err::Failure(CsvError::InvalidLineEnding)),
+                    None => Err(Err::Incomplete(Needed::new(1))),
+                };
+            }
+            Some(_) => return Err(Err::Failure(CsvError::InvalidQuote)),
+            None => return at_end(eof, || (rest, fields)),
+        }
+    }
+}
+
+/// Parses a record and its line ending.
+///
+/// Fields borrow the input, unless they contain escaped quotes.
+pub fn record(i: &[u8]) -> IResult<&[u8], Vec<Cow<'_, [u8]>>, CsvError> {
+    fields(i, false)
+}
+
+/// Parses a record and its line ending, returning owned fields.
+pub fn record_owned(i: &[u8]) -> IResult<&[u8], Vec<Vec<u8>>, CsvError> {
+    let (i, fields) = fields(i, false)?;
+    Ok((i, fields.into_iter().map(Cow::into_owned).collect()))
+}
+
+/// Parses a record ending with a line ending or the end of the input.
+///
+/// Fields borrow the input, unless they contain escaped quotes. Empty input
+/// is rejected with `ErrorKind::Eof`.
+pub fn last_record(i: &[u8]) -> IResult<&[u8], Vec<Cow<'_, [u8]>>, CsvError> {
+    fields(i, true)
+}
+
+/// Parses a record ending with a line ending or the end of the input,
+/// returning owned fields.
+pub fn last_record_owned(i: &[u8]) -> IResult<&[u8], Vec<Vec<u8>>, CsvError> {
+    let (i, fields) = fields(i, true)?;
+    Ok((i, fields.into_iter().map(Cow::into_owned).collect()))
+}
@@ -0,0 +1,411 @@
+//! Parsing and serializing frames on a single stream
+//!
+//! [`Framed`] wraps a stream implementing both `AsyncRead` and `AsyncWrite`,
+//! like a TCP connection, into a `futures::Stream` of frames decoded with nom
+//! parsers, and a `futures::Sink` of messages serialized by an [`Encoder`].
+//! The reading side is an `async_bufreader::BufReader`, and messages sent are
+//! buffered, then written when the sink is flushed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use futures::{SinkExt, StreamExt};
+//! use nom::{bytes::streaming::take_until, IResult};
+//! use nom_bufreader::framed::Framed;
+//! use std::io;
+//!
+//! fn line(i: &[u8]) -> IResult<&[u8], String, ()> {
+//!     let (i, line) = take_until("\n")(i)?;
+//!     Ok((&i[1..], String::from_utf8_lossy(line).into_owned()))
+//! }
+//!
+//! fn reply(msg: String, dst: &mut Vec<u8>) -> io::Result<()> {
+//!     dst.extend_from_slice(msg.as_bytes());
+//!     dst.push(b'\n');
+//!     Ok(())
+//! }
+//!
+//! # futures::executor::block_on(async {
+//! // the cursor stands for a connection: data is written after the input
+//! let stream = futures::io::Cursor::new(b"hello\nworld\n".to_vec());
+//! let mut framed = Framed::new(stream, line, reply);
+//!
+//! assert_eq!(framed.next().await.unwrap().unwrap(), "hello");
+//! assert_eq!(framed.next().await.unwrap().unwrap(), "world");
+//! assert!(framed.next().await.is_none());
+//!
+//! framed.send("bye".to_string()).await.unwrap();
+//! let (reader, _) = framed.into_parts();
+//! assert_eq!(reader.into_inner().into_inner(), b"hello\nworld\nbye\n");
+//! # });
+//! ```
+use super::async_bufreader::BufReader;
+use super::write_buffer::WriteBuffer;
+use super::Error;
+use futures::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+use futures::ready;
+use futures::task::{Context, Poll};
+use futures::{Sink, Stream};
+use nom::{Err, IResult, Offset};
+use pin_project_lite::pin_project;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+
+/// Size of the pending outbound data over which `poll_ready` writes it
+/// before accepting more messages.
+const BACKPRESSURE_BOUNDARY: usize = 8 * 1024;
+
+/// Decodes frames from the data read by a [`Framed`].
+///
+/// This is implemented for nom parsers of the form
+/// `fn(&[u8]) -> IResult<&[u8], O, E>`, where the output and error do not
+/// borrow from the input.
+pub trait Decoder {
+    /// The type of decoded frames.
+    type Item;
+    /// The parser's error type.
+    type Error;
+
+    /// Decodes a frame from the beginning of `input`, returning the remaining
+    /// input. Returns `Err::Incomplete` if more data is needed.
+    fn decode<'a>(&mut self, input: &'a [u8]) -> IResult<&'a [u8], Self::Item, Self::Error>;
+}
+
+impl<F, O, E> Decoder for F
+where
+    F: for<'a> FnMut(&'a [u8]) -> IResult<&'a [u8], O, E>,
+{
+    type Item = O;
+    type Error = E;
+
+    fn decode<'a>(&mut self, input: &'a [u8]) -> IResult<&'a [u8], O, E> {
+        self(input)
+    }
+}
+
+/// Serializes the messages sent to a [`Framed`].
+///
+/// This is implemented for functions of the form
+/// `fn(Item, &mut Vec<u8>) -> io::Result<()>`.
+pub trait Encoder<Item> {
+    /// The error returned by the sink. Errors from writing to the stream are
+    /// converted to it.
+    type Error: From<io::Error>;
+
+    /// Appends the serialized `item` to `dst`. If this returns an error,
+    /// the data it appended is discarded.
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> Result<(), Self::Error>;
+}
+
+impl<F, Item> Encoder<Item> for F
+where
+    F: FnMut(Item, &mut Vec<u8>) -> io::Result<()>,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> io::Result<()> {
+        self(item, dst)
+    }
+}
+
+pin_project! {
+    /// A `Stream` of frames parsed from `T` with `Dec`, and a `Sink` of
+    /// messages serialized to `T` with `Enc`.
+    ///
+    /// The stream ends when the underlying stream ends between frames. If it
+    /// ends in the middle of a frame, the stream returns `Error::Eof`, and if
+    /// a frame does not fit in the maximum capacity of the reader's buffer,
+    /// it returns `Error::TooLarge`. After a parser error, the end of the
+    /// stream, or a frame too large, no more frames are returned.
+    ///
+    /// Messages sent through the sink are buffered, and written to the
+    /// underlying stream when it is flushed, or when too much data is
+    /// pending. When the `Framed` is dropped, data that was not flushed is
+    /// discarded.
+    pub struct Framed<T, Dec, Enc> {
+        #[pin]
+        reader: BufReader<T>,
+        decoder: Dec,
+        encoder: Enc,
+        write_buf: WriteBuffer,
+        done: bool,
+    }
+}
+
+impl<T: AsyncRead, Dec, Enc> Framed<T, Dec, Enc> {
+    /// Creates a `Framed` reading and writing on `inner`, with a default
+    /// read buffer capacity.
+    pub fn new(inner: T, decoder: Dec, encoder: Enc) -> Self {
+        Self::from_reader(BufReader::new(inner), decoder, encoder)
+    }
+
+    /// Creates a `Framed` from an existing reader, keeping the data already
+    /// in its buffer, and its configuration.
+    pub fn from_reader(reader: BufReader<T>, decoder: Dec, encoder: Enc) -> Self {
+        Self {
+            reader,
+            decoder,
+            encoder,
+            write_buf: WriteBuffer::new(BACKPRESSURE_BOUNDARY),
+            done: false,
+        }
+    }
+}
+
+impl<T, Dec, Enc> Framed<T, Dec, Enc> {
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &BufReader<T> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Note that care must be taken to avoid tampering with the state of the
+    /// stream, like consuming part of a frame.
+    pub fn get_mut(&mut self) -> &mut BufReader<T> {
+        &mut self.reader
+    }
+
+    /// Gets a reference to the decoder.
+    pub fn decoder(&self) -> &Dec {
+        &self.decoder
+    }
+
+    /// Gets a mutable reference to the decoder.
+    pub fn decoder_mut(&mut self) -> &mut Dec {
+        &mut self.decoder
+    }
+
+    /// Gets a reference to the encoder.
+    pub fn encoder(&self) -> &Enc {
+        &self.encoder
+    }
+
+    /// Gets a mutable reference to the encoder.
+    pub fn encoder_mut(&mut self) -> &mut Enc {
+        &mut self.encoder
+    }
+
+    /// Returns the serialized messages that were not written yet.
+    pub fn write_buffer(&self) -> &[u8] {
+        self.write_buf.data()
+    }
+
+    /// Unwraps this `Framed`, returning the underlying reader with the data
+    /// that was read but not decoded yet, and the serialized messages that
+    /// were not written yet.
+    pub fn into_parts(mut self) -> (BufReader<T>, Vec<u8>) {
+        let data = self.write_buf.take_data();
+        (self.reader, data)
+    }
+}
+
+impl<T: AsyncWrite, Dec, Enc> Framed<T, Dec, Enc> {
+    /// Writes the pending messages to the underlying stream, without
+    /// flushing it.
+    fn poll_write_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        while !this.write_buf.is_empty() {
+            match ready!(this.reader.as_mut().poll_write(cx, this.write_buf.data()))? {
+                0 => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write frame to stream",
+                    )));
+                }
+                n => this.write_buf.consume(n),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T, Dec, Enc> Stream for Framed<T, Dec, Enc>
+where
+    T: AsyncRead,
+    Dec: Decoder,
+{
+    type Item = Result<Dec::Item, Error<Dec::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let res = match this.decoder.decode(this.reader.buffer()) {
+                Err(Err::Error(e)) => Some(Err(Error::Error(e))),
+                Err(Err::Failure(e)) => Some(Err(Error::Failure(e))),
+                Err(Err::Incomplete(_)) => None,
+                Ok((i, o)) => {
+                    let offset = this.reader.buffer().offset(i);
+                    this.reader.as_mut().consume(offset);
+                    return Poll::Ready(Some(Ok(o)));
+                }
+            };
+
+            if let Some(err) = res {
+                *this.done = true;
+                return Poll::Ready(Some(err));
+            }
+
+            let len = this.reader.buffer().len();
+            if len == this.reader.max_capacity() {
+                // the frame cannot fit in the buffer
+                *this.done = true;
+                return Poll::Ready(Some(Err(Error::TooLarge)));
+            }
+
+            let filled = ready!(this.reader.as_mut().poll_fill_buf(cx))?.len();
+            if filled == len {
+                *this.done = true;
+                return Poll::Ready(if len == 0 {
+                    None
+                } else {
+                    Some(Err(Error::Eof))
+                });
+            }
+        }
+    }
+}
+
+impl<T, Dec, Enc, Item> Sink<Item> for Framed<T, Dec, Enc>
+where
+    T: AsyncWrite,
+    Enc: Encoder<Item>,
+{
+    type Error = Enc::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.write_buf.data().len() >= BACKPRESSURE_BOUNDARY {
+            ready!(self.poll_write_buf(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        let this = self.project();
+        let encoder = this.encoder;
+        this.write_buf.extend_with(|dst| encoder.encode(item, dst))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_buf(cx))?;
+        ready!(self.project().reader.poll_flush(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_buf(cx))?;
+        ready!(self.project().reader.poll_close(cx))?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: fmt::Debug, Dec, Enc> fmt::Debug for Framed<T, Dec, Enc> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Framed")
+            .field("reader", &self.reader)
+            .field("write_buffer", &self.write_buf.data().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BufReaderBuilder;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::{SinkExt, StreamExt};
+    use nom::bytes::streaming::take_until;
+
+    fn line(i: &[u8]) -> IResult<&[u8], Vec<u8>, ()> {
+        let (i, line) = take_until("\n")(i)?;
+        Ok((&i[1..], line.to_vec()))
+    }
+
+    /// Writes a line, failing after appending messages starting with `!`.
+    fn encode(msg: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+        dst.extend_from_slice(msg);
+        if msg.starts_with(b"!") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid"));
+        }
+        dst.push(b'\n');
+        Ok(())
+    }
+
+    type Lines = Framed<
+        Cursor<Vec<u8>>,
+        fn(&[u8]) -> IResult<&[u8], Vec<u8>, ()>,
+        fn(&[u8], &mut Vec<u8>) -> io::Result<()>,
+    >;
+
+    fn lines(data: &[u8], capacity: usize, max_capacity: usize) -> Lines {
+        let reader = BufReaderBuilder::new()
+            .capacity(capacity)
+            .max_capacity(max_capacity)
+            .build_async(Cursor::new(data.to_vec()));
+        Framed::from_reader(reader, line, encode)
+    }
+
+    #[test]
+    fn frames_across_refills() {
+        let mut framed = lines(b"abc\ndef\n", 5, 5);
+        block_on(async {
+            assert_eq!(framed.next().await.unwrap().unwrap(), b"abc");
+            assert_eq!(framed.next().await.unwrap().unwrap(), b"def");
+            assert!(framed.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn frame_larger_than_the_buffer() {
+        let mut framed = lines(b"abcdefgh\n", 4, 4);
+        block_on(async {
+            assert!(matches!(framed.next().await, Some(Err(Error::TooLarge))));
+            assert!(framed.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn frame_at_max_capacity() {
+        // the line ending is part of the frame
+        let mut framed = lines(b"abcdefg\n", 4, 8);
+        block_on(async {
+            assert_eq!(framed.next().await.unwrap().unwrap(), b"abcdefg");
+        });
+
+        let mut framed = lines(b"abcdefgh\n", 4, 8);
+        block_on(async {
+            assert!(matches!(framed.next().await, Some(Err(Error::TooLarge))));
+        });
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_frame() {
+        let mut framed = lines(b"abc\nde", 16, 16);
+        block_on(async {
+            assert_eq!(framed.next().await.unwrap().unwrap(), b"abc");
+            assert!(matches!(framed.next().await, Some(Err(Error::Eof))));
+            assert!(framed.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn encoder_error_discards_partial_message() {
+        let mut framed = lines(b"", 16, 16);
+        block_on(async {
+            framed.feed(&b"abc"[..]).await.unwrap();
+            assert!(framed.feed(&b"!bad"[..]).await.is_err());
+            assert_eq!(framed.write_buffer(), b"abc\n");
+
+            framed.flush().await.unwrap();
+            assert!(framed.write_buffer().is_empty());
+        });
+        let (reader, _) = framed.into_parts();
+        assert_eq!(reader.into_inner().into_inner(), b"abc\n");
+    }
+}
@@ -0,0 +1,528 @@
+//! HTTP/1.1 request and response head parsing
+//!
+//! Streaming nom parsers for the grammar of
+//! [RFC 9112](https://www.rfc-editor.org/rfc/rfc9112): request line, status
+//! line and header fields, returning types from the
+//! [http](https://docs.rs/http) crate. [`request`] and [`response`] parse a
+//! complete head with default limits, and can be used directly with `Parse`
+//! and `AsyncParse`. [`RequestParser`] and [`ResponseParser`] do the same
+//! with configurable limits on the number of headers and the size of the
+//! head. The body is left in the reader.
+//!
+//! Lines can end with a bare LF instead of CRLF, as the RFC allows
+//! recipients to accept. Obsolete line folding in header values is rejected.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::http::{request, HttpError};
+//! use nom_bufreader::{Error, Parse};
+//!
+//! fn main() -> Result<(), Error<HttpError>> {
+//!     let mut reader = BufReader::new(
+//!         &b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n"[..],
+//!     );
+//!
+//!     let req = reader.parse(request)?;
+//!     assert_eq!(req.method(), http::Method::GET);
+//!     assert_eq!(req.uri().path(), "/index.html");
+//!     assert_eq!(req.headers()["host"], "example.com");
+//!     Ok(())
+//! }
+//! ```
+//!
+//! With limits:
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::http::{HttpError, RequestParser};
+//! use nom_bufreader::{Error, Parse};
+//!
+//! let mut reader = BufReader::new(&b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n"[..]);
+//!
+//! let res = reader.parse(RequestParser::new().max_headers(1));
+//! assert!(matches!(res, Err(Error::Failure(HttpError::TooManyHeaders))));
+//! ```
+use ::http::header::{HeaderMap, HeaderName, HeaderValue};
+use ::http::{Method, Request, Response, StatusCode, Uri, Version};
+use nom::branch::alt;
+use nom::bytes::streaming::{tag, take, take_till, take_till1, take_while, take_while1};
+use nom::character::streaming::char;
+use nom::error::{ErrorKind, ParseError};
+use nom::{Err, IResult, Offset, Parser};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Default maximum number of header fields in a head.
+pub const DEFAULT_MAX_HEADERS: usize = 100;
+
+/// Default maximum size of a head, in bytes, including the request or status
+/// line and the final empty line.
+pub const DEFAULT_MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Errors returned by the HTTP parsers.
+///
+/// Syntax errors are returned as `Err::Error`, so the parsers can be used in
+/// `alt` combinators. Invalid values in a well formed head, and exceeded
+/// limits, are returned as `Err::Failure`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HttpError {
+    /// The input does not follow the HTTP grammar
+    Syntax(ErrorKind),
+    InvalidMethod,
+    InvalidUri,
+    /// The version is not HTTP/1.0 or HTTP/1.1
+    InvalidVersion,
+    InvalidStatus,
+    InvalidHeaderName,
+    InvalidHeaderValue,
+    /// The head has more header fields than allowed
+    TooManyHeaders,
+    /// The head is larger than allowed
+    HeadTooLarge,
+    /// A chunk size in a chunked body does not fit in 64 bits
+    InvalidChunkSize,
+    /// A chunk size line, with its chunk extensions, is longer than allowed
+    ChunkSizeLineTooLarge,
+    /// The trailer section of a chunked body is larger than allowed
+    TrailersTooLarge,
+}
+
+impl<I> ParseError<I> for HttpError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        HttpError::Syntax(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Syntax(kind) => write!(f, "invalid HTTP syntax ({:?})", kind),
+            HttpError::InvalidMethod => f.write_str("invalid method"),
+            HttpError::InvalidUri => f.write_str("invalid request target"),
+            HttpError::InvalidVersion => f.write_str("unsupported HTTP version"),
+            HttpError::InvalidStatus => f.write_str("invalid status code"),
+            HttpError::InvalidHeaderName => f.write_str("invalid header name"),
+            HttpError::InvalidHeaderValue => f.write_str("invalid header value"),
+            HttpError::TooManyHeaders => f.write_str("too many headers"),
+            HttpError::HeadTooLarge => f.write_str("head too large"),
+            HttpError::InvalidChunkSize => f.write_str("invalid chunk size"),
+            HttpError::ChunkSizeLineTooLarge => f.write_str("chunk size line too large"),
+            HttpError::TrailersTooLarge => f.write_str("trailer section too large"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+fn failure<I, O>(e: HttpError) -> IResult<I, O, HttpError> {
+    Err(Err::Failure(e))
+}
+
+/// tchar, the characters allowed in tokens like methods and header names
+fn is_tchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+fn is_ctl(c: u8) -> bool {
+    c < 0x20 || c == 0x7f
+}
+
+fn is_ows(c: u8) -> bool {
+    c == b' ' || c == b'\t'
+}
+
+fn is_eol(c: u8) -> bool {
+    c == b'\r' || c == b'\n'
+}
+
+/// Parses a line ending, CRLF or a bare LF.
+pub fn crlf(i: &[u8]) -> IResult<&[u8], (), HttpError> {
+    let (i, _) = alt((tag("\r\n"), tag("\n")))(i)?;
+    Ok((i, ()))
+}
+
+/// Parses an HTTP version, `HTTP/1.0` or `HTTP/1.1`.
+pub fn version(i: &[u8]) -> IResult<&[u8], Version, HttpError> {
+    let (i, _) = tag("HTTP/")(i)?;
+    let (i, v) = take(3usize)(i)?;
+    match v {
+        b"1.1" => Ok((i, Version::HTTP_11)),
+        b"1.0" => Ok((i, Version::HTTP_10)),
+        _ => failure(HttpError::InvalidVersion),
+    }
+}
+
+/// Parses a request line, like `GET /index.html HTTP/1.1\r\n`.
+pub fn request_line(i: &[u8]) -> IResult<&[u8], (Method, Uri, Version), HttpError> {
+    let (i, method) = take_while1(is_tchar)(i)?;
+    let (i, _) = char(' ')(i)?;
+    let (i, target) = take_till1(|c| c == b' ' || is_ctl(c))(i)?;
+    let (i, _) = char(' ')(i)?;
+    let (i, version) = version(i)?;
+    let (i, _) = crlf(i)?;
+
+    let method = match Method::from_bytes(method) {
+        Ok(method) => method,
+        Err(_) => return failure(HttpError::InvalidMethod),
+    };
+    let uri = match Uri::try_from(target) {
+        Ok(uri) => uri,
+        Err(_) => return failure(HttpError::InvalidUri),
+    };
+    Ok((i, (method, uri, version)))
+}
+
+/// Parses a status line, like `HTTP/1.1 200 OK\r\n`, returning the reason
+/// phrase as is. The reason phrase can be empty.
+pub fn status_line(i: &[u8]) -> IResult<&[u8], (Version, StatusCode, &[u8]), HttpError> {
+    let (i, version) = version(i)?;
+    let (i, _) = char(' ')(i)?;
+    let (i, code) = take(3usize)(i)?;
+    let (i, reason) = take_till(is_eol)(i)?;
+    let (i, _) = crlf(i)?;
+
+    let reason = match reason.split_first() {
+        None => reason,
+        Some((b' ', reason)) => reason,
+        Some(_) => return failure(HttpError::InvalidStatus),
+    };
+    if !code.iter().all(u8::is_ascii_digit) {
+        return failure(HttpError::InvalidStatus);
+    }
+    let status = match StatusCode::from_bytes(code) {
+        Ok(status) => status,
+        Err(_) => return failure(HttpError::InvalidStatus),
+    };
+    Ok((i, (version, status, reason)))
+}
+
+/// Parses a header field line, like `Host: example.com\r\n`.
+///
+/// The whitespace around the value is removed.
+pub fn header(i: &[u8]) -> IResult<&[u8], (HeaderName, HeaderValue), HttpError> {
+    let (i, name) = take_while1(is_tchar)(i)?;
+    let (i, _) = char(':')(i)?;
+    let (i, _) = take_while(is_ows)(i)?;
+    let (i, value) = take_till(is_eol)(i)?;
+    let (i, _) = crlf(i)?;
+
+    let end = value.iter().rposition(|c| !is_ows(*c)).map_or(0, |p| p + 1);
+    let name = match HeaderName::from_bytes(name) {
+        Ok(name) => name,
+        Err(_) => return failure(HttpError::InvalidHeaderName),
+    };
+    let value = match HeaderValue::from_bytes(&value[..end]) {
+        Ok(value) => value,
+        Err(_) => return failure(HttpError::InvalidHeaderValue),
+    };
+    Ok((i, (name, value)))
+}
+
+/// Parses header fields until the empty line ending the head, with the
+/// default limit on their number.
+pub fn headers(i: &[u8]) -> IResult<&[u8], HeaderMap, HttpError> {
+    headers_with_limit(i, DEFAULT_MAX_HEADERS)
+}
+
+fn headers_with_limit(mut i: &[u8], max_headers: usize) -> IResult<&[u8], HeaderMap, HttpError> {
+    let mut map = HeaderMap::new();
+    let mut count = 0;
+    loop {
+        match crlf(i) {
+            Ok((i, ())) => return Ok((i, map)),
+            Err(Err::Error(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        if count == max_headers {
+            return failure(HttpError::TooManyHeaders);
+        }
+        let (rest, (name, value)) = header(i)?;
+        map.append(name, value);
+        count += 1;
+        i = rest;
+    }
+}
+
+/// Parses a request head with the default limits.
+pub fn request(i: &[u8]) -> IResult<&[u8], Request<()>, HttpError> {
+    RequestParser::new().parse(i)
+}
+
+/// Parses a response head with the default limits.
+pub fn response(i: &[u8]) -> IResult<&[u8], Response<()>, HttpError> {
+    ResponseParser::new().parse(i)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Limits {
+    max_headers: usize,
+    max_head_size: usize,
+}
+
+impl Limits {
+    fn new() -> Limits {
+        Limits {
+            max_headers: DEFAULT_MAX_HEADERS,
+            max_head_size: DEFAULT_MAX_HEAD_SIZE,
+        }
+    }
+
+    /// Fails if the head parsed from `i` is larger than allowed, or if it is
+    /// incomplete and already larger.
+    fn check<'a, O>(
+        &self,
+        i: &'a [u8],
+        res: IResult<&'a [u8], O, HttpError>,
+    ) -> IResult<&'a [u8], O, HttpError> {
+        match res {
+            Err(Err::Incomplete(_)) if i.len() >= self.max_head_size => {
+                failure(HttpError::HeadTooLarge)
+            }
+            Ok((rest, _)) if i.offset(rest) > self.max_head_size => {
+                failure(HttpError::HeadTooLarge)
+            }
+            res => res,
+        }
+    }
+}
+
+/// Parser for a request head, with configurable limits.
+///
+/// The reader's buffer must be able to hold the largest head allowed,
+/// otherwise filling it fails before the size limit is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestParser {
+    limits: Limits,
+}
+
+impl RequestParser {
+    /// Creates a parser with [`DEFAULT_MAX_HEADERS`] and
+    /// [`DEFAULT_MAX_HEAD_SIZE`] as limits.
+    pub fn new() -> RequestParser {
+        RequestParser {
+            limits: Limits::new(),
+        }
+    }
+
+    /// Sets the maximum number of header fields.
+    pub fn max_headers(mut self, max_headers: usize) -> RequestParser {
+        self.limits.max_headers = max_headers;
+        self
+    }
+
+    /// Sets the maximum size of the head, in bytes.
+    pub fn max_head_size(mut self, max_head_size: usize) -> RequestParser {
+        self.limits.max_head_size = max_head_size;
+        self
+    }
+}
+
+impl Default for RequestParser {
+    fn default() -> RequestParser {
+        RequestParser::new()
+    }
+}
+
+impl<'a> Parser<&'a [u8], Request<()>, HttpError> for RequestParser {
+    fn parse(&mut self, input: &'a [u8]) -> IResult<&'a [u8], Request<()>, HttpError> {
+        let res = request_line(input).and_then(|(i, (method, uri, version))| {
+            let (i, headers) = headers_with_limit(i, self.limits.max_headers)?;
+
+            let mut request = Request::new(());
+            *request.method_mut() = method;
+            *request.uri_mut() = uri;
+            *request.version_mut() = version;
+            *request.headers_mut() = headers;
+            Ok((i, request))
+        });
+        self.limits.check(input, res)
+    }
+}
+
+/// Parser for a response head, with configurable limits.
+///
+/// The reader's buffer must be able to hold the largest head allowed,
+/// otherwise filling it fails before the size limit is reached. The reason
+/// phrase is not kept, use [`status_line`] to get it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResponseParser {
+    limits: Limits,
+}
+
+impl ResponseParser {
+    /// Creates a parser with [`DEFAULT_MAX_HEADERS`] and
+    /// [`DEFAULT_MAX_HEAD_SIZE`] as limits.
+    pub fn new() -> ResponseParser {
+        ResponseParser {
+            limits: Limits::new(),
+        }
+    }
+
+    /// Sets the maximum number of header fields.
+    pub fn max_headers(mut self, max_headers: usize) -> ResponseParser {
+        self.limits.max_headers = max_headers;
+        self
+    }
+
+    /// Sets the maximum size of the head, in bytes.
+    pub fn max_head_size(mut self, max_head_size: usize) -> ResponseParser {
+        self.limits.max_head_size = max_head_size;
+        self
+    }
+}
+
+impl Default for ResponseParser {
+    fn default() -> ResponseParser {
+        ResponseParser::new()
+    }
+}
+
+impl<'a> Parser<&'a [u8], Response<()>, HttpError> for ResponseParser {
+    fn parse(&mut self, input: &'a [u8]) -> IResult<&'a [u8], Response<()>, HttpError> {
+        let res = status_line(input).and_then(|(i, (version, status, _))| {
+            let (i, headers) = headers_with_limit(i, self.limits.max_headers)?;
+
+            let mut response = Response::new(());
+            *response.status_mut() = status;
+            *response.version_mut() = version;
+            *response.headers_mut() = headers;
+            Ok((i, response))
+        });
+        self.limits.check(input, res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bufreader::BufReader;
+    use crate::builder::BufReaderBuilder;
+    use crate::{Error, Parse};
+
+    // 18 bytes, and 6 more for each header
+    fn head(headers: usize) -> Vec<u8> {
+        let mut head = b"GET / HTTP/1.1\r\n".to_vec();
+        for _ in 0..headers {
+            head.extend_from_slice(b"A: 1\r\n");
+        }
+        head.extend_from_slice(b"\r\n");
+        head
+    }
+
+    fn request_error(mut parser: RequestParser, i: &[u8]) -> HttpError {
+        match parser.parse(i) {
+            Err(Err::Failure(e)) => e,
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    fn reader(data: &[u8], max_capacity: usize) -> BufReader<&[u8]> {
+        BufReaderBuilder::new()
+            .capacity(16)
+            .max_capacity(max_capacity)
+            .build(data)
+    }
+
+    #[test]
+    fn max_headers() {
+        let (_, req) = RequestParser::new().max_headers(2).parse(&head(2)).unwrap();
+        assert_eq!(req.headers().len(), 2);
+    }
+
+    #[test]
+    fn too_many_headers() {
+        let parser = RequestParser::new().max_headers(2);
+        assert_eq!(request_error(parser, &head(3)), HttpError::TooManyHeaders);
+    }
+
+    #[test]
+    fn default_max_headers() {
+        assert!(request(&head(DEFAULT_MAX_HEADERS)).is_ok());
+        let error = request_error(RequestParser::new(), &head(DEFAULT_MAX_HEADERS + 1));
+        assert_eq!(error, HttpError::TooManyHeaders);
+    }
+
+    #[test]
+    fn max_head_size() {
+        assert!(RequestParser::new()
+            .max_head_size(24)
+            .parse(&head(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn head_too_large() {
+        let parser = RequestParser::new().max_head_size(23);
+        assert_eq!(request_error(parser, &head(1)), HttpError::HeadTooLarge);
+    }
+
+    #[test]
+    fn incomplete_head_under_max_head_size() {
+        let data = head(1);
+        let res = RequestParser::new().max_head_size(24).parse(&data[..23]);
+        assert!(matches!(res, Err(Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn incomplete_head_at_max_head_size() {
+        // the rest of the head cannot fit anymore
+        let parser = RequestParser::new().max_head_size(23);
+        assert_eq!(
+            request_error(parser, &head(1)[..23]),
+            HttpError::HeadTooLarge
+        );
+    }
+
+    #[test]
+    fn response_head_too_large() {
+        let data = b"HTTP/1.1 200 OK\r\nA: 1\r\n\r\n";
+        assert!(ResponseParser::new().max_head_size(25).parse(data).is_ok());
+        let res = ResponseParser::new().max_head_size(24).parse(data);
+        assert_eq!(res.unwrap_err(), Err::Failure(HttpError::HeadTooLarge));
+    }
+
+    #[test]
+    fn response_too_many_headers() {
+        let data = b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\n\r\n";
+        let res = ResponseParser::new().max_headers(1).parse(data);
+        assert_eq!(res.unwrap_err(), Err::Failure(HttpError::TooManyHeaders));
+    }
+
+    #[test]
+    fn bare_lf() {
+        let (_, req) = request(b"GET / HTTP/1.1\nA: 1\n\n").unwrap();
+        assert_eq!(req.headers()["a"], "1");
+    }
+
+    #[test]
+    fn read_head_across_refills() {
+        let mut data = head(4);
+        data.extend_from_slice(b"body");
+        let mut reader = reader(&data, 64);
+        let req = reader.parse(request).unwrap();
+        assert_eq!(req.headers().len(), 4);
+        assert_eq!(reader.buffer(), b"body");
+    }
+
+    #[test]
+    fn read_head_too_large() {
+        let data = head(8);
+        let mut reader = reader(&data, 64);
+        let res = reader.parse(RequestParser::new().max_head_size(48));
+        assert!(matches!(res, Err(Error::Failure(HttpError::HeadTooLarge))));
+    }
+
+    #[test]
+    fn read_eof_in_head() {
+        let data = head(2);
+        let mut reader = reader(&data[..20], 64);
+        assert!(matches!(reader.parse(request), Err(Error::Eof)));
+    }
+}
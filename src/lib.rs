@@ -4,7 +4,40 @@
 //! It will hide for you the [Incomplete](https://docs.rs/nom/7.0.0/nom/enum.Err.html#variant.Incomplete) handling in nom for streaming parsers, retrying and refilling buffers automatically.
 //!
 //! For synchronous io, use `bufreader::BufReader`, while for asynchronous
-//! IO, you should use `async_bufreader::BufReader`
+//! IO, you should use `async_bufreader::BufReader`. Both can be configured
+//! with a `builder::BufReaderBuilder`, to let the buffer grow when a parser
+//! needs more data than it can hold
+//!
+//! `framed::Framed` combines an asynchronous reader with buffered writes, as a
+//! `Stream` of parsed frames and a `Sink` of serialized messages
+//!
+//! The `prefix` module reads length prefixed frames, and the `csv` module
+//! parses CSV records whose quoted fields can span any number of refills
+//!
+//! With the `bytes` feature, `bytes_bufreader::BufReader` stores its data in a
+//! `bytes::BytesMut` and can return parsed payloads as `bytes::Bytes` handles
+//! without copying them
+//!
+//! With the `mmap` feature, `mmap::MmapReader` parses directly from a memory
+//! mapped file, with the same `Parse` interface
+//!
+//! With the `http` feature, the `http` module provides parsers for HTTP/1.1
+//! request and response heads, returning types from the `http` crate, and
+//! `chunked::ChunkedReader` decodes chunked bodies
+//!
+//! With the `ndjson` feature, `ndjson::NdjsonReader` decodes newline delimited
+//! JSON records with `serde_json`, one line at a time
+//!
+//! With the `resp` feature, the `resp` module reads and writes values of the
+//! Redis protocol
+//!
+//! With the `mqtt` feature, the `mqtt` module parses MQTT 3.1.1 and 5.0
+//! control packets, with a maximum packet size
+//!
+//! With the `cookie-factory` feature, `bufwriter::BufWriter` and
+//! `async_bufwriter::BufWriter` write the other half of a connection, running
+//! [cookie_factory](https://docs.rs/cookie-factory) serializers directly into
+//! their buffer
 //!
 //! # Examples
 //!
@@ -85,14 +118,51 @@ use futures::{
 
 #[cfg(feature = "async")]
 pub mod async_bufreader;
+#[cfg(all(feature = "async", feature = "cookie-factory"))]
+pub mod async_bufwriter;
+mod buffer;
 pub mod bufreader;
+#[cfg(feature = "cookie-factory")]
+pub mod bufwriter;
+pub mod builder;
+#[cfg(feature = "bytes")]
+pub mod bytes_bufreader;
+#[cfg(feature = "http")]
+pub mod chunked;
+pub mod csv;
+#[cfg(feature = "async")]
+pub mod framed;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "ndjson")]
+pub mod ndjson;
+pub mod pool;
+pub mod prefix;
+pub mod replay;
+#[cfg(feature = "resp")]
+pub mod resp;
+#[cfg(feature = "async")]
+pub mod websocket;
+#[cfg(any(feature = "async", feature = "cookie-factory"))]
+mod write_buffer;
 
+/// Errors returned by the readers and parsers of this crate.
+///
+/// New variants can be added in minor releases, so matches on this type need
+/// a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error<E> {
     Error(E),
     Failure(E),
     Io(io::Error),
     Eof,
+    /// The data exceeds the maximum length allowed by the caller
+    TooLarge,
 }
 
 impl<E> From<io::Error> for Error<E> {
@@ -176,10 +246,11 @@ impl<R: Read, O, E, P> Parse<O, E, P> for bufreader::BufReader<R> {
                         return Err(Error::Io(e));
                     }
 
+                    let len = self.buffer().len();
                     match self.fill_buf() {
                         Err(e) => error = Some(e),
                         Ok(s) => {
-                            if s.is_empty() {
+                            if s.len() == len {
                                 eof = true;
                             }
                         }
@@ -190,6 +261,74 @@ impl<R: Read, O, E, P> Parse<O, E, P> for bufreader::BufReader<R> {
     }
 }
 
+#[cfg(feature = "bytes")]
+impl<R: Read, O, E, P> Parse<O, E, P> for bytes_bufreader::BufReader<R> {
+    fn parse(&mut self, mut p: P) -> Result<O, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], O, E>,
+    {
+        let mut eof = false;
+        let mut error = None;
+        loop {
+            let opt = match p.parse(self.buffer()) {
+                Err(Err::Error(e)) => return Err(Error::Error(e)),
+                Err(Err::Failure(e)) => return Err(Error::Failure(e)),
+                Err(Err::Incomplete(_)) => None,
+                Ok((i, o)) => {
+                    let offset = self.buffer().offset(i);
+                    Some((offset, o))
+                }
+            };
+
+            match opt {
+                Some((sz, o)) => {
+                    self.consume(sz);
+                    return Ok(o);
+                }
+                None => {
+                    if eof {
+                        return Err(Error::Eof);
+                    }
+
+                    if let Some(e) = error.take() {
+                        return Err(Error::Io(e));
+                    }
+
+                    let len = self.buffer().len();
+                    match self.fill_buf() {
+                        Err(e) => error = Some(e),
+                        Ok(s) => {
+                            if s.len() == len {
+                                eof = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<O, E, P> Parse<O, E, P> for mmap::MmapReader {
+    fn parse(&mut self, mut p: P) -> Result<O, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], O, E>,
+    {
+        // the whole file is already in the buffer, there is nothing to refill
+        match p.parse(self.buffer()) {
+            Err(Err::Error(e)) => Err(Error::Error(e)),
+            Err(Err::Failure(e)) => Err(Error::Failure(e)),
+            Err(Err::Incomplete(_)) => Err(Error::Eof),
+            Ok((i, o)) => {
+                let offset = self.buffer().offset(i);
+                self.consume(offset);
+                Ok(o)
+            }
+        }
+    }
+}
+
 #[cfg(feature = "async")]
 #[async_trait]
 pub trait AsyncParse<O, E, P> {
@@ -242,6 +381,7 @@ impl<R: AsyncRead + Unpin + Send, O: Send, E, P> AsyncParse<O, E, P>
     where
         for<'a> P: Parser<&'a [u8], O, E> + Send + 'async_trait,
     {
+        let mut eof = false;
         loop {
             let opt =
                     //match p(input.buffer()) {
@@ -263,7 +403,55 @@ impl<R: AsyncRead + Unpin + Send, O: Send, E, P> AsyncParse<O, E, P>
                     return Ok(o);
                 }
                 None => {
-                    self.fill_buf().await?;
+                    if eof {
+                        return Err(Error::Eof);
+                    }
+
+                    let len = self.buffer().len();
+                    if self.fill_buf().await?.len() == len {
+                        eof = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "async", feature = "bytes"))]
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send, O: Send, E, P> AsyncParse<O, E, P>
+    for bytes_bufreader::BufReader<R>
+{
+    async fn parse(&mut self, mut p: P) -> Result<O, Error<E>>
+    where
+        for<'a> P: Parser<&'a [u8], O, E> + Send + 'async_trait,
+    {
+        let mut eof = false;
+        loop {
+            let opt = match p.parse(self.buffer()) {
+                Err(Err::Error(e)) => return Err(Error::Error(e)),
+                Err(Err::Failure(e)) => return Err(Error::Failure(e)),
+                Err(Err::Incomplete(_)) => None,
+                Ok((i, o)) => {
+                    let offset = self.buffer().offset(i);
+                    Some((offset, o))
+                }
+            };
+
+            match opt {
+                Some((sz, o)) => {
+                    self.consume_unpin(sz);
+                    return Ok(o);
+                }
+                None => {
+                    if eof {
+                        return Err(Error::Eof);
+                    }
+
+                    let len = self.buffer().len();
+                    if self.fill_buf().await?.len() == len {
+                        eof = true;
+                    }
                 }
             }
         }
@@ -0,0 +1,163 @@
+// a reader exposing a memory mapped file as its buffer
+//
+// the whole file is visible to the parser at once, so there is never any
+// refill: an incomplete parse means we reached the end of the file
+
+use memmap2::Mmap;
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The `MmapReader` struct gives access to a memory mapped file through the
+/// same interface as [`bufreader::BufReader`](crate::bufreader::BufReader).
+///
+/// Instead of copying the file's content in a fixed size buffer, the mapping
+/// itself is used as the buffer, and `MmapReader` only tracks how much of it
+/// was consumed. It implements [`Parse`](crate::Parse): since the whole file
+/// is already available, a parser returning `Incomplete` results in
+/// [`Error::Eof`](crate::Error::Eof), like it would with a `BufReader`
+/// reaching the end of the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nom::{bytes::streaming::take_until, IResult};
+/// use nom_bufreader::mmap::MmapReader;
+/// use nom_bufreader::{Error, Parse};
+///
+/// fn line(i: &[u8]) -> IResult<&[u8], usize, ()> {
+///     let (i, l) = take_until("\n")(i)?;
+///     Ok((&i[1..], l.len()))
+/// }
+///
+/// fn main() -> Result<(), Error<()>> {
+///     let mut reader = unsafe { MmapReader::open("log.txt")? };
+///
+///     let len = reader.parse(line)?;
+///     println!("first line is {} bytes long, now at offset {}", len, reader.position());
+///     Ok(())
+/// }
+/// ```
+pub struct MmapReader {
+    map: Mmap,
+    pos: usize,
+}
+
+impl MmapReader {
+    /// Opens the file at `path` and maps it in memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified (by this process or another one) while
+    /// it is mapped, otherwise the data seen by parsers could change under
+    /// them. See [`memmap2::Mmap::map`].
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapReader> {
+        let file = File::open(path)?;
+        MmapReader::map(&file)
+    }
+
+    /// Maps `file` in memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified (by this process or another one) while
+    /// it is mapped, otherwise the data seen by parsers could change under
+    /// them. See [`memmap2::Mmap::map`].
+    pub unsafe fn map(file: &File) -> io::Result<MmapReader> {
+        Ok(MmapReader::from_mmap(Mmap::map(file)?))
+    }
+
+    /// Creates a `MmapReader` from an existing mapping, starting at its
+    /// beginning.
+    pub fn from_mmap(map: Mmap) -> MmapReader {
+        MmapReader { map, pos: 0 }
+    }
+
+    /// Gets a reference to the underlying mapping.
+    pub fn get_ref(&self) -> &Mmap {
+        &self.map
+    }
+
+    /// Returns the data that was not consumed yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.map[self.pos..]
+    }
+
+    /// Returns the offset of the consumed data, from the start of the file.
+    pub fn position(&self) -> u64 {
+        self.pos as u64
+    }
+
+    /// Returns the size of the mapped file.
+    pub fn len(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    /// Returns `true` if the mapped file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Unwraps this `MmapReader`, returning the underlying mapping.
+    pub fn into_inner(self) -> Mmap {
+        self.map
+    }
+}
+
+impl Read for MmapReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let nread = self.buffer().read(buf)?;
+        self.consume(nread);
+        Ok(nread)
+    }
+}
+
+impl BufRead for MmapReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.buffer())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = cmp::min(self.pos + amt, self.map.len());
+    }
+}
+
+impl Seek for MmapReader {
+    /// Seek to an offset, in bytes, in the mapped file.
+    ///
+    /// Seeking past the end of the file is allowed, and leaves the reader at
+    /// the end of the file.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => (self.map.len() as u64).checked_add_signed(n),
+            SeekFrom::Current(n) => (self.pos as u64).checked_add_signed(n),
+        };
+
+        match new_pos {
+            Some(n) => {
+                self.pos = cmp::min(n, self.map.len() as u64) as usize;
+                Ok(self.pos as u64)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos as u64)
+    }
+}
+
+impl fmt::Debug for MmapReader {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("MmapReader")
+            .field("position", &self.pos)
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
@@ -0,0 +1,1285 @@
+//! MQTT control packets, for versions 3.1.1 and 5.0
+//!
+//! Every [MQTT](https://mqtt.org/mqtt-specification/) packet starts with a
+//! fixed header: the packet type and flags, then the length of the rest of
+//! the packet, encoded on one to four bytes. [`fixed_header`] parses it, and
+//! [`PacketParser`] parses whole packets of the main types, once they are
+//! complete in the buffer, for one version of the protocol. CONNECT packets
+//! announce the version used on the connection, and are parsed whatever the
+//! version of the parser is.
+//!
+//! A packet is rejected as soon as its fixed header shows that it is larger
+//! than the maximum packet size, without waiting for the rest of it.
+//! [`read_packet`] uses the maximum capacity of the reader's buffer as that
+//! limit, so the packets accepted are the ones the buffer can hold.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::builder::BufReaderBuilder;
+//! use nom_bufreader::mqtt::{read_packet, MqttError, Packet, QoS, Version};
+//! use nom_bufreader::Error;
+//!
+//! let data = [
+//!     // CONNECT, with the client identifier "abc"
+//!     0x10, 0x10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0x02, 0x00, 0x3c, 0x00, 0x00,
+//!     0x03, b'a', b'b', b'c',
+//!     // PUBLISH "hi" on "a/b" at QoS 1, with a payload format indicator
+//!     0x32, 0x0c, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x0a, 0x02, 0x01, 0x01, b'h', b'i',
+//!     // PINGREQ
+//!     0xc0, 0x00,
+//!     // PUBLISH of 200 bytes
+//!     0x30, 0xc8, 0x01,
+//! ];
+//!
+//! # futures::executor::block_on(async {
+//! let mut reader = BufReaderBuilder::new()
+//!     .capacity(32)
+//!     .max_capacity(64)
+//!     .build_async(&data[..]);
+//!
+//! let version = match read_packet(&mut reader, Version::V5).await.unwrap() {
+//!     Packet::Connect(connect) => {
+//!         assert_eq!(connect.client_id, "abc");
+//!         assert_eq!(connect.keep_alive, 60);
+//!         connect.version
+//!     }
+//!     packet => panic!("unexpected packet: {:?}", packet),
+//! };
+//!
+//! match read_packet(&mut reader, version).await.unwrap() {
+//!     Packet::Publish(publish) => {
+//!         assert_eq!(publish.topic, "a/b");
+//!         assert_eq!(publish.qos, QoS::AtLeastOnce);
+//!         assert_eq!(publish.packet_id, Some(10));
+//!         assert_eq!(publish.payload, b"hi");
+//!     }
+//!     packet => panic!("unexpected packet: {:?}", packet),
+//! }
+//!
+//! assert_eq!(read_packet(&mut reader, version).await.unwrap(), Packet::PingReq);
+//! assert!(matches!(
+//!     read_packet(&mut reader, version).await,
+//!     Err(Error::Failure(MqttError::PacketTooLarge))
+//! ));
+//! # });
+//! ```
+use super::async_bufreader::BufReader;
+use super::{AsyncParse, Error};
+use futures::io::AsyncRead;
+use nom::bytes::streaming::take;
+use nom::error::{ErrorKind, ParseError};
+use nom::number::streaming::{be_u16, be_u32, be_u8};
+use nom::{Err, IResult, Offset, Parser};
+use std::fmt;
+
+/// Largest remaining length that can be encoded in a fixed header.
+pub const MAX_REMAINING_LEN: u32 = 268_435_455;
+
+/// Errors returned by the MQTT parsers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MqttError {
+    /// The input does not follow the packet grammar
+    Syntax(ErrorKind),
+    /// Reserved packet type
+    InvalidPacketType(u8),
+    /// Invalid flags in the fixed header, or in a CONNECT packet
+    InvalidFlags,
+    /// A packet identifier of 0
+    InvalidPacketId,
+    /// A variable byte integer longer than four bytes, or not encoded on the
+    /// smallest number of bytes
+    InvalidVarint,
+    /// The content of the packet does not match its remaining length
+    InvalidLength,
+    /// The packet is larger than the maximum packet size
+    PacketTooLarge,
+    /// The protocol name is not `MQTT`, or the level is not 4 or 5
+    UnsupportedProtocol,
+    InvalidQoS,
+    /// A string that is not valid UTF-8
+    InvalidString,
+    /// Unknown property identifier
+    InvalidProperty(u32),
+    /// A topic name with wildcard characters
+    InvalidTopic,
+    /// A SUBSCRIBE or UNSUBSCRIBE packet without topic filters
+    MissingTopicFilter,
+    /// Reserved bits, or an invalid retain handling, in subscription options
+    InvalidSubscriptionOptions,
+    /// A SUBACK, or an MQTT 5 UNSUBACK, without reason codes
+    MissingReasonCode,
+}
+
+impl<I> ParseError<I> for MqttError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        MqttError::Syntax(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MqttError::Syntax(kind) => write!(f, "invalid MQTT syntax ({:?})", kind),
+            MqttError::InvalidPacketType(t) => write!(f, "invalid packet type {}", t),
+            MqttError::InvalidFlags => f.write_str("invalid flags"),
+            MqttError::InvalidPacketId => f.write_str("invalid packet identifier"),
+            MqttError::InvalidVarint => f.write_str("invalid variable byte integer"),
+            MqttError::InvalidLength => f.write_str("invalid remaining length"),
+            MqttError::PacketTooLarge => f.write_str("packet too large"),
+            MqttError::UnsupportedProtocol => f.write_str("unsupported protocol"),
+            MqttError::InvalidQoS => f.write_str("invalid QoS"),
+            MqttError::InvalidString => f.write_str("invalid UTF-8 string"),
+            MqttError::InvalidProperty(id) => write!(f, "invalid property {:#x}", id),
+            MqttError::InvalidTopic => f.write_str("wildcard in topic name"),
+            MqttError::MissingTopicFilter => f.write_str("missing topic filter"),
+            MqttError::InvalidSubscriptionOptions => f.write_str("invalid subscription options"),
+            MqttError::MissingReasonCode => f.write_str("missing reason code"),
+        }
+    }
+}
+
+impl std::error::Error for MqttError {}
+
+fn failure<O>(e: MqttError) -> Result<O, Err<MqttError>> {
+    Err(Err::Failure(e))
+}
+
+/// Version of the protocol used on a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// MQTT 3.1.1, protocol level 4
+    V311,
+    /// MQTT 5.0, protocol level 5
+    V5,
+}
+
+/// The type of a control packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketType {
+    Connect,
+    ConnAck,
+    Publish,
+    PubAck,
+    PubRec,
+    PubRel,
+    PubComp,
+    Subscribe,
+    SubAck,
+    Unsubscribe,
+    UnsubAck,
+    PingReq,
+    PingResp,
+    Disconnect,
+    /// Only used by MQTT 5
+    Auth,
+}
+
+impl PacketType {
+    /// Returns the packet type for the high 4 bits of the first byte of a
+    /// packet, or `None` if it is reserved.
+    pub fn from_u8(t: u8) -> Option<PacketType> {
+        Some(match t {
+            1 => PacketType::Connect,
+            2 => PacketType::ConnAck,
+            3 => PacketType::Publish,
+            4 => PacketType::PubAck,
+            5 => PacketType::PubRec,
+            6 => PacketType::PubRel,
+            7 => PacketType::PubComp,
+            8 => PacketType::Subscribe,
+            9 => PacketType::SubAck,
+            10 => PacketType::Unsubscribe,
+            11 => PacketType::UnsubAck,
+            12 => PacketType::PingReq,
+            13 => PacketType::PingResp,
+            14 => PacketType::Disconnect,
+            15 => PacketType::Auth,
+            _ => return None,
+        })
+    }
+
+    /// Flags required in the fixed header, for all types but PUBLISH.
+    fn flags(self) -> Option<u8> {
+        match self {
+            PacketType::Publish => None,
+            PacketType::PubRel | PacketType::Subscribe | PacketType::Unsubscribe => Some(0b0010),
+            _ => Some(0),
+        }
+    }
+}
+
+/// The fixed header starting every packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedHeader {
+    pub packet_type: PacketType,
+    /// The low 4 bits of the first byte
+    pub flags: u8,
+    /// The length of the packet after the fixed header
+    pub remaining_len: u32,
+}
+
+/// Parses a variable byte integer, of one to four bytes.
+///
+/// The value must be encoded on the smallest number of bytes: a last byte of
+/// 0 after other bytes, like `0x80 0x00` for 0, is rejected.
+pub fn variable_int(i: &[u8]) -> IResult<&[u8], u32, MqttError> {
+    let mut value = 0u32;
+    let mut i = i;
+    for index in 0..4 {
+        let (rest, byte) = be_u8(i)?;
+        value |= u32::from(byte & 0x7f) << (7 * index);
+        i = rest;
+        if byte & 0x80 == 0 {
+            if byte == 0 && index > 0 {
+                return failure(MqttError::InvalidVarint);
+            }
+            return Ok((i, value));
+        }
+    }
+    failure(MqttError::InvalidVarint)
+}
+
+/// Parses a fixed header.
+///
+/// Flags are checked for all packet types but PUBLISH, where they hold the
+/// DUP, QoS and RETAIN fields.
+pub fn fixed_header(i: &[u8]) -> IResult<&[u8], FixedHeader, MqttError> {
+    let (i, first) = be_u8(i)?;
+    let packet_type = match PacketType::from_u8(first >> 4) {
+        Some(packet_type) => packet_type,
+        None => return failure(MqttError::InvalidPacketType(first >> 4)),
+    };
+
+    let flags = first & 0x0f;
+    if matches!(packet_type.flags(), Some(expected) if flags != expected) {
+        return failure(MqttError::InvalidFlags);
+    }
+
+    let (i, remaining_len) = variable_int(i)?;
+    Ok((
+        i,
+        FixedHeader {
+            packet_type,
+            flags,
+            remaining_len,
+        },
+    ))
+}
+
+/// Quality of service of a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+impl QoS {
+    fn from_bits(bits: u8) -> Result<QoS, Err<MqttError>> {
+        match bits {
+            0 => Ok(QoS::AtMostOnce),
+            1 => Ok(QoS::AtLeastOnce),
+            2 => Ok(QoS::ExactlyOnce),
+            _ => failure(MqttError::InvalidQoS),
+        }
+    }
+}
+
+/// The value of an MQTT 5 property.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PropertyValue {
+    Byte(u8),
+    U16(u16),
+    U32(u32),
+    /// A variable byte integer
+    Varint(u32),
+    String(String),
+    Binary(Vec<u8>),
+    /// A name and a value, for user properties
+    Pair(String, String),
+}
+
+/// An MQTT 5 property, like `0x26` for a user property.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property {
+    pub id: u32,
+    pub value: PropertyValue,
+}
+
+/// A message published by the server when the client disconnects
+/// unexpectedly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Will {
+    pub properties: Vec<Property>,
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Connect {
+    pub version: Version,
+    /// Clean session in MQTT 3.1.1
+    pub clean_start: bool,
+    pub keep_alive: u16,
+    pub properties: Vec<Property>,
+    pub client_id: String,
+    pub will: Option<Will>,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnAck {
+    pub session_present: bool,
+    /// Return code in MQTT 3.1.1
+    pub reason_code: u8,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Publish {
+    pub dup: bool,
+    pub qos: QoS,
+    pub retain: bool,
+    pub topic: String,
+    /// Only present for QoS 1 and 2
+    pub packet_id: Option<u16>,
+    pub properties: Vec<Property>,
+    pub payload: Vec<u8>,
+}
+
+/// PUBACK, PUBREC, PUBREL and PUBCOMP packets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ack {
+    pub packet_id: u16,
+    /// Always 0 (success) in MQTT 3.1.1
+    pub reason_code: u8,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subscribe {
+    pub packet_id: u16,
+    pub properties: Vec<Property>,
+    /// Topic filters with their subscription options: the maximum QoS in the
+    /// two low bits, and in MQTT 5 the no local, retain as published and
+    /// retain handling flags
+    pub filters: Vec<(String, u8)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubAck {
+    pub packet_id: u16,
+    pub properties: Vec<Property>,
+    pub reason_codes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unsubscribe {
+    pub packet_id: u16,
+    pub properties: Vec<Property>,
+    pub filters: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsubAck {
+    pub packet_id: u16,
+    pub properties: Vec<Property>,
+    /// Always empty in MQTT 3.1.1
+    pub reason_codes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disconnect {
+    /// Always 0 (success) in MQTT 3.1.1
+    pub reason_code: u8,
+    pub properties: Vec<Property>,
+}
+
+/// AUTH packets, only used by MQTT 5.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Auth {
+    /// 0x00 (success), 0x18 (continue authentication) or 0x19
+    /// (re-authenticate)
+    pub reason_code: u8,
+    pub properties: Vec<Property>,
+}
+
+/// A control packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Packet {
+    Connect(Connect),
+    ConnAck(ConnAck),
+    Publish(Publish),
+    PubAck(Ack),
+    PubRec(Ack),
+    PubRel(Ack),
+    PubComp(Ack),
+    Subscribe(Subscribe),
+    SubAck(SubAck),
+    Unsubscribe(Unsubscribe),
+    UnsubAck(UnsubAck),
+    PingReq,
+    PingResp,
+    Disconnect(Disconnect),
+    Auth(Auth),
+}
+
+fn string(i: &[u8]) -> IResult<&[u8], String, MqttError> {
+    let (i, data) = binary(i)?;
+    match String::from_utf8(data) {
+        Ok(s) => Ok((i, s)),
+        Err(_) => failure(MqttError::InvalidString),
+    }
+}
+
+fn binary(i: &[u8]) -> IResult<&[u8], Vec<u8>, MqttError> {
+    let (i, len) = be_u16(i)?;
+    let (i, data) = take(len)(i)?;
+    Ok((i, data.to_vec()))
+}
+
+fn property(i: &[u8]) -> IResult<&[u8], Property, MqttError> {
+    let (i, id) = variable_int(i)?;
+    let (i, value) = match id {
+        0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2a => {
+            be_u8(i).map(|(i, v)| (i, PropertyValue::Byte(v)))?
+        }
+        0x13 | 0x21 | 0x22 | 0x23 => be_u16(i).map(|(i, v)| (i, PropertyValue::U16(v)))?,
+        0x02 | 0x11 | 0x18 | 0x27 => be_u32(i).map(|(i, v)| (i, PropertyValue::U32(v)))?,
+        0x0b => variable_int(i).map(|(i, v)| (i, PropertyValue::Varint(v)))?,
+        0x03 | 0x08 | 0x12 | 0x15 | 0x1a | 0x1c | 0x1f => {
+            string(i).map(|(i, v)| (i, PropertyValue::String(v)))?
+        }
+        0x09 | 0x16 => binary(i).map(|(i, v)| (i, PropertyValue::Binary(v)))?,
+        0x26 => {
+            let (i, name) = string(i)?;
+            let (i, value) = string(i)?;
+            (i, PropertyValue::Pair(name, value))
+        }
+        id => return failure(MqttError::InvalidProperty(id)),
+    };
+    Ok((i, Property { id, value }))
+}
+
+/// Parses the properties of an MQTT 5 packet, or nothing in MQTT 3.1.1.
+fn properties(i: &[u8], version: Version) -> IResult<&[u8], Vec<Property>, MqttError> {
+    if version == Version::V311 {
+        return Ok((i, Vec::new()));
+    }
+
+    let (i, len) = variable_int(i)?;
+    let (i, mut data) = take(len)(i)?;
+    let mut properties = Vec::new();
+    while !data.is_empty() {
+        let (rest, property) = property(data)?;
+        properties.push(property);
+        data = rest;
+    }
+    Ok((i, properties))
+}
+
+fn packet_id(i: &[u8]) -> IResult<&[u8], u16, MqttError> {
+    let (i, id) = be_u16(i)?;
+    if id == 0 {
+        return failure(MqttError::InvalidPacketId);
+    }
+    Ok((i, id))
+}
+
+/// Parses the reason code and properties ending ack, DISCONNECT and AUTH
+/// packets, which MQTT 5 can omit.
+fn reason(i: &[u8], version: Version) -> IResult<&[u8], (u8, Vec<Property>), MqttError> {
+    let (i, reason_code) = if i.is_empty() {
+        (i, 0)
+    } else if version == Version::V5 {
+        be_u8(i)?
+    } else {
+        return failure(MqttError::InvalidLength);
+    };
+    let (i, properties) = if i.is_empty() {
+        (i, Vec::new())
+    } else {
+        properties(i, version)?
+    };
+    end(i)?;
+    Ok((i, (reason_code, properties)))
+}
+
+/// Fails if the packet has data left after its content.
+fn end(i: &[u8]) -> Result<(), Err<MqttError>> {
+    if i.is_empty() {
+        Ok(())
+    } else {
+        failure(MqttError::InvalidLength)
+    }
+}
+
+fn connect(i: &[u8]) -> IResult<&[u8], Connect, MqttError> {
+    let (i, name) = binary(i)?;
+    let (i, level) = be_u8(i)?;
+    let version = match (&name[..], level) {
+        (b"MQTT", 4) => Version::V311,
+        (b"MQTT", 5) => Version::V5,
+        _ => return failure(MqttError::UnsupportedProtocol),
+    };
+
+    let (i, flags) = be_u8(i)?;
+    let (i, keep_alive) = be_u16(i)?;
+    let (i, connect_properties) = properties(i, version)?;
+    let (mut i, client_id) = string(i)?;
+
+    let will_flag = flags & 0x04 != 0;
+    let will_qos = QoS::from_bits((flags >> 3) & 0x03)?;
+    let will_retain = flags & 0x20 != 0;
+    if flags & 0x01 != 0 || (!will_flag && (will_qos != QoS::AtMostOnce || will_retain)) {
+        return failure(MqttError::InvalidFlags);
+    }
+    // MQTT 5 allows a password without a user name
+    if version == Version::V311 && flags & 0xc0 == 0x40 {
+        return failure(MqttError::InvalidFlags);
+    }
+
+    let mut will = None;
+    if will_flag {
+        let (rest, will_properties) = properties(i, version)?;
+        let (rest, topic) = string(rest)?;
+        let (rest, payload) = binary(rest)?;
+        will = Some(Will {
+            properties: will_properties,
+            topic,
+            payload,
+            qos: will_qos,
+            retain: will_retain,
+        });
+        i = rest;
+    }
+
+    let mut username = None;
+    if flags & 0x80 != 0 {
+        let (rest, s) = string(i)?;
+        username = Some(s);
+        i = rest;
+    }
+
+    let mut password = None;
+    if flags & 0x40 != 0 {
+        let (rest, data) = binary(i)?;
+        password = Some(data);
+        i = rest;
+    }
+
+    end(i)?;
+    Ok((
+        i,
+        Connect {
+            version,
+            clean_start: flags & 0x02 != 0,
+            keep_alive,
+            properties: connect_properties,
+            client_id,
+            will,
+            username,
+            password,
+        },
+    ))
+}
+
+fn connack(i: &[u8], version: Version) -> IResult<&[u8], ConnAck, MqttError> {
+    let (i, flags) = be_u8(i)?;
+    if flags & 0xfe != 0 {
+        return failure(MqttError::InvalidFlags);
+    }
+    let (i, reason_code) = be_u8(i)?;
+    let (i, properties) = properties(i, version)?;
+    end(i)?;
+    Ok((
+        i,
+        ConnAck {
+            session_present: flags & 0x01 != 0,
+            reason_code,
+            properties,
+        },
+    ))
+}
+
+fn publish(i: &[u8], flags: u8, version: Version) -> IResult<&[u8], Publish, MqttError> {
+    let qos = QoS::from_bits((flags >> 1) & 0x03)?;
+    let dup = flags & 0x08 != 0;
+    if dup && qos == QoS::AtMostOnce {
+        return failure(MqttError::InvalidFlags);
+    }
+
+    let (i, topic) = string(i)?;
+    if topic.contains(['+', '#']) {
+        return failure(MqttError::InvalidTopic);
+    }
+    let (i, packet_id) = match qos {
+        QoS::AtMostOnce => (i, None),
+        _ => packet_id(i).map(|(i, id)| (i, Some(id)))?,
+    };
+    let (i, properties) = properties(i, version)?;
+    Ok((
+        &i[i.len()..],
+        Publish {
+            dup,
+            qos,
+            retain: flags & 0x01 != 0,
+            topic,
+            packet_id,
+            properties,
+            payload: i.to_vec(),
+        },
+    ))
+}
+
+fn ack(i: &[u8], version: Version) -> IResult<&[u8], Ack, MqttError> {
+    let (i, packet_id) = packet_id(i)?;
+    let (i, (reason_code, properties)) = reason(i, version)?;
+    Ok((
+        i,
+        Ack {
+            packet_id,
+            reason_code,
+            properties,
+        },
+    ))
+}
+
+/// Checks the options of a subscription: the maximum QoS, and in MQTT 5 the
+/// retain handling, the other bits being reserved.
+fn subscription_options(options: u8, version: Version) -> Result<(), Err<MqttError>> {
+    QoS::from_bits(options & 0x03)?;
+    let valid = match version {
+        Version::V311 => options & 0xfc == 0,
+        Version::V5 => options & 0xc0 == 0 && (options >> 4) & 0x03 != 3,
+    };
+    if valid {
+        Ok(())
+    } else {
+        failure(MqttError::InvalidSubscriptionOptions)
+    }
+}
+
+fn subscribe(i: &[u8], version: Version) -> IResult<&[u8], Subscribe, MqttError> {
+    let (i, packet_id) = packet_id(i)?;
+    let (mut i, properties) = properties(i, version)?;
+    let mut filters = Vec::new();
+    while !i.is_empty() {
+        let (rest, filter) = string(i)?;
+        let (rest, options) = be_u8(rest)?;
+        subscription_options(options, version)?;
+        filters.push((filter, options));
+        i = rest;
+    }
+    if filters.is_empty() {
+        return failure(MqttError::MissingTopicFilter);
+    }
+    Ok((
+        i,
+        Subscribe {
+            packet_id,
+            properties,
+            filters,
+        },
+    ))
+}
+
+fn suback(i: &[u8], version: Version) -> IResult<&[u8], SubAck, MqttError> {
+    let (i, packet_id) = packet_id(i)?;
+    let (i, properties) = properties(i, version)?;
+    if i.is_empty() {
+        return failure(MqttError::MissingReasonCode);
+    }
+    Ok((
+        &i[i.len()..],
+        SubAck {
+            packet_id,
+            properties,
+            reason_codes: i.to_vec(),
+        },
+    ))
+}
+
+fn unsubscribe(i: &[u8], version: Version) -> IResult<&[u8], Unsubscribe, MqttError> {
+    let (i, packet_id) = packet_id(i)?;
+    let (mut i, properties) = properties(i, version)?;
+    let mut filters = Vec::new();
+    while !i.is_empty() {
+        let (rest, filter) = string(i)?;
+        filters.push(filter);
+        i = rest;
+    }
+    if filters.is_empty() {
+        return failure(MqttError::MissingTopicFilter);
+    }
+    Ok((
+        i,
+        Unsubscribe {
+            packet_id,
+            properties,
+            filters,
+        },
+    ))
+}
+
+fn unsuback(i: &[u8], version: Version) -> IResult<&[u8], UnsubAck, MqttError> {
+    let (i, packet_id) = packet_id(i)?;
+    let (i, properties) = properties(i, version)?;
+    match version {
+        Version::V311 => end(i)?,
+        Version::V5 if i.is_empty() => return failure(MqttError::MissingReasonCode),
+        Version::V5 => {}
+    }
+    Ok((
+        &i[i.len()..],
+        UnsubAck {
+            packet_id,
+            properties,
+            reason_codes: i.to_vec(),
+        },
+    ))
+}
+
+fn disconnect(i: &[u8], version: Version) -> IResult<&[u8], Disconnect, MqttError> {
+    let (i, (reason_code, properties)) = reason(i, version)?;
+    Ok((
+        i,
+        Disconnect {
+            reason_code,
+            properties,
+        },
+    ))
+}
+
+fn auth(i: &[u8]) -> IResult<&[u8], Auth, MqttError> {
+    let (i, (reason_code, properties)) = reason(i, Version::V5)?;
+    Ok((
+        i,
+        Auth {
+            reason_code,
+            properties,
+        },
+    ))
+}
+
+/// Parses the content of a packet, after its fixed header.
+fn body(header: FixedHeader, version: Version, i: &[u8]) -> IResult<&[u8], Packet, MqttError> {
+    Ok(match header.packet_type {
+        PacketType::Connect => connect(i).map(|(i, p)| (i, Packet::Connect(p)))?,
+        PacketType::ConnAck => connack(i, version).map(|(i, p)| (i, Packet::ConnAck(p)))?,
+        PacketType::Publish => {
+            publish(i, header.flags, version).map(|(i, p)| (i, Packet::Publish(p)))?
+        }
+        PacketType::PubAck => ack(i, version).map(|(i, p)| (i, Packet::PubAck(p)))?,
+        PacketType::PubRec => ack(i, version).map(|(i, p)| (i, Packet::PubRec(p)))?,
+        PacketType::PubRel => ack(i, version).map(|(i, p)| (i, Packet::PubRel(p)))?,
+        PacketType::PubComp => ack(i, version).map(|(i, p)| (i, Packet::PubComp(p)))?,
+        PacketType::Subscribe => subscribe(i, version).map(|(i, p)| (i, Packet::Subscribe(p)))?,
+        PacketType::SubAck => suback(i, version).map(|(i, p)| (i, Packet::SubAck(p)))?,
+        PacketType::Unsubscribe => {
+            unsubscribe(i, version).map(|(i, p)| (i, Packet::Unsubscribe(p)))?
+        }
+        PacketType::UnsubAck => unsuback(i, version).map(|(i, p)| (i, Packet::UnsubAck(p)))?,
+        PacketType::PingReq => {
+            end(i)?;
+            (i, Packet::PingReq)
+        }
+        PacketType::PingResp => {
+            end(i)?;
+            (i, Packet::PingResp)
+        }
+        PacketType::Disconnect => {
+            disconnect(i, version).map(|(i, p)| (i, Packet::Disconnect(p)))?
+        }
+        PacketType::Auth if version == Version::V5 => auth(i).map(|(i, p)| (i, Packet::Auth(p)))?,
+        PacketType::Auth => return failure(MqttError::InvalidPacketType(15)),
+    })
+}
+
+/// Parser for a whole packet, for one version of the protocol.
+///
+/// The parser returns `Incomplete` until the whole packet is available.
+/// Once it is, errors in its content are returned as `Err::Failure`, and a
+/// packet whose content is shorter or longer than its remaining length is
+/// rejected with [`MqttError::InvalidLength`].
+///
+/// # Examples
+///
+/// ```rust
+/// use nom::{Err, Parser};
+/// use nom_bufreader::mqtt::{Ack, MqttError, Packet, PacketParser, Version};
+///
+/// let mut parser = PacketParser::new(Version::V5);
+///
+/// // PUBACK for the packet 10, without reason code
+/// let packet = [0x40, 0x02, 0x00, 0x0a];
+/// assert!(matches!(parser.parse(&packet[..3]), Err(Err::Incomplete(_))));
+/// assert_eq!(
+///     parser.parse(&packet[..]),
+///     Ok((
+///         &[][..],
+///         Packet::PubAck(Ack {
+///             packet_id: 10,
+///             reason_code: 0,
+///             properties: Vec::new(),
+///         })
+///     ))
+/// );
+///
+/// // packet identifiers cannot be 0
+/// assert_eq!(
+///     parser.parse(&[0x40, 0x02, 0x00, 0x00][..]),
+///     Err(Err::Failure(MqttError::InvalidPacketId))
+/// );
+/// /// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketParser {
+    version: Version,
+    max_packet_size: usize,
+}
+
+impl PacketParser {
+    /// Creates a parser for `version`, accepting packets of any size the
+    /// protocol allows.
+    pub fn new(version: Version) -> PacketParser {
+        PacketParser {
+            version,
+            // the fixed header is at most 5 bytes
+            max_packet_size: MAX_REMAINING_LEN as usize + 5,
+        }
+    }
+
+    /// Sets the maximum size of a packet, in bytes, including its fixed
+    /// header.
+    ///
+    /// The reader's buffer must be able to hold the largest packet allowed,
+    /// otherwise filling it fails before the size limit is reached.
+    pub fn max_packet_size(mut self, max_packet_size: usize) -> PacketParser {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+}
+
+impl<'a> Parser<&'a [u8], Packet, MqttError> for PacketParser {
+    fn parse(&mut self, input: &'a [u8]) -> IResult<&'a [u8], Packet, MqttError> {
+        let (i, header) = fixed_header(input)?;
+        let size = input.offset(i) as u64 + u64::from(header.remaining_len);
+        if size > self.max_packet_size as u64 {
+            return failure(MqttError::PacketTooLarge);
+        }
+
+        let (i, data) = take(header.remaining_len)(i)?;
+        match body(header, self.version, data) {
+            Ok((_, packet)) => Ok((i, packet)),
+            Err(Err::Incomplete(_)) => failure(MqttError::InvalidLength),
+            Err(Err::Error(e)) | Err(Err::Failure(e)) => failure(e),
+        }
+    }
+}
+
+/// Reads a packet for `version` from `reader`.
+///
+/// Packets larger than the maximum capacity of the reader's buffer are
+/// rejected with [`MqttError::PacketTooLarge`] as soon as their fixed header
+/// is read.
+pub async fn read_packet<R>(
+    reader: &mut BufReader<R>,
+    version: Version,
+) -> Result<Packet, Error<MqttError>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let parser = PacketParser::new(version).max_packet_size(reader.max_capacity());
+    reader.parse(parser).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BufReaderBuilder;
+    use futures::executor::block_on;
+
+    fn parse(version: Version, packet: &[u8]) -> IResult<&[u8], Packet, MqttError> {
+        PacketParser::new(version).parse(packet)
+    }
+
+    fn packet_error(version: Version, packet: &[u8]) -> MqttError {
+        match parse(version, packet) {
+            Err(Err::Failure(e)) => e,
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    fn read(data: &[u8], max_capacity: usize) -> Result<Packet, Error<MqttError>> {
+        let mut reader = BufReaderBuilder::new()
+            .capacity(8)
+            .max_capacity(max_capacity)
+            .build_async(data);
+        block_on(read_packet(&mut reader, Version::V311))
+    }
+
+    #[test]
+    fn varint_one_byte() {
+        assert_eq!(variable_int(&[0x7f]), Ok((&[][..], 127)));
+    }
+
+    #[test]
+    fn varint_two_bytes() {
+        assert_eq!(variable_int(&[0x80, 0x01]), Ok((&[][..], 128)));
+    }
+
+    #[test]
+    fn varint_maximum() {
+        let data = [0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(variable_int(&data), Ok((&[][..], MAX_REMAINING_LEN)));
+    }
+
+    #[test]
+    fn varint_incomplete() {
+        assert!(matches!(
+            variable_int(&[0xff, 0xff]),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn varint_five_bytes() {
+        let packet = [0x30, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert_eq!(packet_error(Version::V5, &packet), MqttError::InvalidVarint);
+    }
+
+    #[test]
+    fn varint_non_minimal_zero() {
+        assert_eq!(
+            packet_error(Version::V5, &[0xc0, 0x80, 0x00]),
+            MqttError::InvalidVarint
+        );
+    }
+
+    #[test]
+    fn varint_non_minimal_three_bytes() {
+        let packet = [0x30, 0x81, 0x80, 0x00];
+        assert_eq!(packet_error(Version::V5, &packet), MqttError::InvalidVarint);
+    }
+
+    #[test]
+    fn varint_non_minimal_property() {
+        // DISCONNECT with a property length of 0 on two bytes
+        let packet = [0xe0, 0x03, 0x00, 0x80, 0x00];
+        assert_eq!(packet_error(Version::V5, &packet), MqttError::InvalidVarint);
+    }
+
+    #[test]
+    fn subscribe_flags() {
+        assert_eq!(
+            packet_error(Version::V5, &[0x80, 0x00]),
+            MqttError::InvalidFlags
+        );
+    }
+
+    #[test]
+    fn publish_dup_at_qos_0() {
+        let packet = [0x38, 0x03, 0x00, 0x01, b'a'];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidFlags
+        );
+    }
+
+    #[test]
+    fn publish_wildcard_topic() {
+        let packet = [0x30, 0x03, 0x00, 0x01, b'#'];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidTopic
+        );
+    }
+
+    #[test]
+    fn publish_packet_id_0() {
+        let packet = [0x32, 0x05, 0x00, 0x01, b'a', 0x00, 0x00];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn publish_at_qos_0_without_packet_id() {
+        match parse(Version::V311, &[0x30, 0x05, 0x00, 0x01, b'a', 0x00, 0x00]) {
+            Ok((_, Packet::Publish(publish))) => {
+                assert_eq!(publish.packet_id, None);
+                assert_eq!(publish.payload, [0, 0]);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn puback_packet_id_0() {
+        let packet = [0x40, 0x02, 0x00, 0x00];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn pubrec_packet_id_0() {
+        let packet = [0x50, 0x02, 0x00, 0x00];
+        assert_eq!(
+            packet_error(Version::V5, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn pubrel_packet_id_0() {
+        let packet = [0x62, 0x02, 0x00, 0x00];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn pubcomp_packet_id_0() {
+        let packet = [0x70, 0x02, 0x00, 0x00];
+        assert_eq!(
+            packet_error(Version::V5, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn subscribe_packet_id_0() {
+        let packet = [0x82, 0x06, 0x00, 0x00, 0x00, 0x01, b'a', 0x00];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn unsubscribe_packet_id_0() {
+        let packet = [0xa2, 0x05, 0x00, 0x00, 0x00, 0x01, b'a'];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidPacketId
+        );
+    }
+
+    #[test]
+    fn puback_too_short() {
+        assert_eq!(
+            packet_error(Version::V311, &[0x40, 0x01, 0x00]),
+            MqttError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn puback_too_long() {
+        let packet = [0x40, 0x03, 0x00, 0x01, 0x00];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn pingreq_with_content() {
+        assert_eq!(
+            packet_error(Version::V311, &[0xc0, 0x01, 0x00]),
+            MqttError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn auth_in_v311() {
+        let error = packet_error(Version::V311, &[0xf0, 0x00]);
+        assert_eq!(error, MqttError::InvalidPacketType(15));
+    }
+
+    #[test]
+    fn auth() {
+        let expected = Packet::Auth(Auth {
+            reason_code: 0x18,
+            properties: Vec::new(),
+        });
+        assert_eq!(
+            parse(Version::V5, &[0xf0, 0x02, 0x18, 0x00]),
+            Ok((&[][..], expected))
+        );
+    }
+
+    #[test]
+    fn auth_without_reason_code() {
+        let expected = Packet::Auth(Auth {
+            reason_code: 0,
+            properties: Vec::new(),
+        });
+        assert_eq!(parse(Version::V5, &[0xf0, 0x00]), Ok((&[][..], expected)));
+    }
+
+    #[test]
+    fn unknown_property() {
+        let packet = [0xe0, 0x03, 0x00, 0x01, 0x7f];
+        assert_eq!(
+            packet_error(Version::V5, &packet),
+            MqttError::InvalidProperty(0x7f)
+        );
+    }
+
+    #[test]
+    fn subscribe_without_filters() {
+        let packet = [0x82, 0x02, 0x00, 0x01];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::MissingTopicFilter
+        );
+    }
+
+    #[test]
+    fn unsubscribe_without_filters() {
+        let packet = [0xa2, 0x02, 0x00, 0x01];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::MissingTopicFilter
+        );
+    }
+
+    #[test]
+    fn suback_without_reason_codes() {
+        let packet = [0x90, 0x02, 0x00, 0x01];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::MissingReasonCode
+        );
+    }
+
+    #[test]
+    fn suback_v5_without_reason_codes() {
+        let packet = [0x90, 0x03, 0x00, 0x01, 0x00];
+        assert_eq!(
+            packet_error(Version::V5, &packet),
+            MqttError::MissingReasonCode
+        );
+    }
+
+    #[test]
+    fn unsuback_v5_without_reason_codes() {
+        let packet = [0xb0, 0x03, 0x00, 0x01, 0x00];
+        assert_eq!(
+            packet_error(Version::V5, &packet),
+            MqttError::MissingReasonCode
+        );
+    }
+
+    #[test]
+    fn unsuback_v311() {
+        let expected = Packet::UnsubAck(UnsubAck {
+            packet_id: 1,
+            properties: Vec::new(),
+            reason_codes: Vec::new(),
+        });
+        assert_eq!(
+            parse(Version::V311, &[0xb0, 0x02, 0x00, 0x01]),
+            Ok((&[][..], expected))
+        );
+    }
+
+    #[test]
+    fn subscription_options_reserved_bits_v311() {
+        let packet = [0x82, 0x06, 0x00, 0x01, 0x00, 0x01, b'a', 0x04];
+        let error = packet_error(Version::V311, &packet);
+        assert_eq!(error, MqttError::InvalidSubscriptionOptions);
+    }
+
+    #[test]
+    fn subscription_options_reserved_bits_v5() {
+        let packet = [0x82, 0x07, 0x00, 0x01, 0x00, 0x00, 0x01, b'a', 0x40];
+        let error = packet_error(Version::V5, &packet);
+        assert_eq!(error, MqttError::InvalidSubscriptionOptions);
+    }
+
+    #[test]
+    fn subscription_options_retain_handling_3() {
+        let packet = [0x82, 0x07, 0x00, 0x01, 0x00, 0x00, 0x01, b'a', 0x30];
+        let error = packet_error(Version::V5, &packet);
+        assert_eq!(error, MqttError::InvalidSubscriptionOptions);
+    }
+
+    #[test]
+    fn connect_v311_password_without_username() {
+        let packet = [
+            0x10, 0x0e, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x42, 0x00, 0x3c, 0, 0, 0, 0,
+        ];
+        assert_eq!(
+            packet_error(Version::V311, &packet),
+            MqttError::InvalidFlags
+        );
+    }
+
+    #[test]
+    fn max_packet_size() {
+        let packet = [0x30, 0x03, 0x00, 0x01, b'a'];
+        let mut parser = PacketParser::new(Version::V311).max_packet_size(5);
+        assert!(parser.parse(&packet).is_ok());
+    }
+
+    #[test]
+    fn packet_too_large() {
+        let packet = [0x30, 0x03, 0x00, 0x01, b'a'];
+        let res = PacketParser::new(Version::V311)
+            .max_packet_size(4)
+            .parse(&packet);
+        assert_eq!(res, Err(Err::Failure(MqttError::PacketTooLarge)));
+    }
+
+    #[test]
+    fn packet_too_large_from_header() {
+        // only the fixed header is needed to reject the packet
+        let res = PacketParser::new(Version::V311)
+            .max_packet_size(16)
+            .parse(&[0x30, 0x0f]);
+        assert_eq!(res, Err(Err::Failure(MqttError::PacketTooLarge)));
+    }
+
+    #[test]
+    fn read_packet_max_capacity() {
+        let mut packet = vec![0x30, 0x0e, 0x00, 0x01, b'a'];
+        packet.extend_from_slice(&[b'x'; 11]);
+        match read(&packet, 16) {
+            Ok(Packet::Publish(publish)) => assert_eq!(publish.payload, [b'x'; 11]),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn read_packet_too_large() {
+        let mut packet = vec![0x30, 0x0f, 0x00, 0x01, b'a'];
+        packet.extend_from_slice(&[b'x'; 12]);
+        assert!(matches!(
+            read(&packet, 16),
+            Err(Error::Failure(MqttError::PacketTooLarge))
+        ));
+    }
+
+    #[test]
+    fn read_packet_eof() {
+        assert!(matches!(read(&[0x30, 0x03, 0x00], 16), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn read_packet_eof_in_header() {
+        assert!(matches!(read(&[0x30, 0x80], 16), Err(Error::Eof)));
+    }
+}
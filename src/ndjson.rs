@@ -0,0 +1,329 @@
+//! Newline delimited JSON
+//!
+//! [`NdjsonReader`] wraps a [`BufReader`] into an iterator of records, one
+//! JSON document per line, decoded with `serde_json`. Lines are limited to
+//! a maximum length, so a stream without line endings cannot make the
+//! buffer grow without bounds, and the reader's buffer is allowed to grow
+//! to fit that length. Decoding errors carry the line number of the
+//! record, and do not stop the iteration: the next call returns the
+//! following record.
+//!
+//! Lines can end with `\n` or `\r\n`, the last line does not need a line
+//! ending, and blank lines are skipped.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::ndjson::NdjsonReader;
+//! use nom_bufreader::Error;
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, Deserialize, PartialEq)]
+//! struct Event {
+//!     level: String,
+//!     msg: String,
+//! }
+//!
+//! let reader = BufReader::new(
+//!     &b"{\"level\":\"info\",\"msg\":\"started\"}\n\n{\"level\":2}\r\n{\"level\":\"warn\",\"msg\":\"slow\"}"[..],
+//! );
+//! let mut records = NdjsonReader::<_, Event>::new(reader);
+//!
+//! assert_eq!(records.next().unwrap().unwrap().msg, "started");
+//! match records.next().unwrap() {
+//!     Err(Error::Error(e)) => assert_eq!(e.line(), 3),
+//!     res => panic!("unexpected result: {:?}", res),
+//! }
+//! assert_eq!(records.next().unwrap().unwrap().level, "warn");
+//! assert!(records.next().is_none());
+//! ```
+//!
+//! The buffer of a default reader holds 8 KiB, but it grows to read lines up
+//! to the maximum length, and longer lines are rejected:
+//!
+//! ```rust
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::ndjson::NdjsonReader;
+//! use nom_bufreader::Error;
+//!
+//! let mut data = b"\"".to_vec();
+//! data.extend_from_slice(&[b'a'; 10_000]);
+//! data.extend_from_slice(b"\"\n\"");
+//! data.extend_from_slice(&[b'b'; 20_000]);
+//! data.extend_from_slice(b"\"\n");
+//!
+//! let reader = BufReader::new(&data[..]);
+//! let mut records = NdjsonReader::<_, String>::with_max_line_len(reader, 16 * 1024);
+//! assert_eq!(records.next().unwrap().unwrap().len(), 10_000);
+//! assert!(matches!(records.next(), Some(Err(Error::TooLarge))));
+//! assert!(records.next().is_none());
+//! ```
+use super::bufreader::BufReader;
+use super::Error;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::{BufRead, Read};
+use std::marker::PhantomData;
+
+/// Default maximum length of a line, without its line ending.
+pub const DEFAULT_MAX_LINE_LEN: usize = 64 * 1024;
+
+/// A record that could not be decoded.
+#[derive(Debug)]
+pub struct JsonError {
+    line: u64,
+    error: serde_json::Error,
+}
+
+impl JsonError {
+    /// Returns the line number of the record, starting at 1.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Returns the error returned by `serde_json`. Its line and column are
+    /// relative to the record.
+    pub fn get_ref(&self) -> &serde_json::Error {
+        &self.error
+    }
+
+    /// Unwraps this `JsonError`, returning the error returned by
+    /// `serde_json`.
+    pub fn into_inner(self) -> serde_json::Error {
+        self.error
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid record at line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Iterator decoding one record of type `T` per line from a [`BufReader`].
+///
+/// Records that are not valid JSON, or do not match `T`, are returned as
+/// `Error::Error`, and the iteration continues on the next line. A line
+/// longer than the maximum length is returned as `Error::TooLarge`, and IO
+/// errors as `Error::Io`: after those, the iterator ends. The line ending is
+/// not counted in the length.
+///
+/// The maximum capacity of the reader's buffer is raised if needed, so that
+/// it can hold the longest line allowed with its line ending. The buffer
+/// only grows, following the reader's growth policy, when a long line comes.
+/// A larger maximum capacity set with
+/// [`BufReaderBuilder`](crate::builder::BufReaderBuilder) is kept.
+pub struct NdjsonReader<R, T> {
+    reader: BufReader<R>,
+    max_line_len: usize,
+    line: u64,
+    done: bool,
+    record: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: DeserializeOwned> NdjsonReader<R, T> {
+    /// Creates an iterator over the records starting at the current
+    /// position of `reader`, with lines of at most
+    /// [`DEFAULT_MAX_LINE_LEN`] bytes.
+    pub fn new(reader: BufReader<R>) -> NdjsonReader<R, T> {
+        Self::with_max_line_len(reader, DEFAULT_MAX_LINE_LEN)
+    }
+
+    /// Creates an iterator over the records starting at the current
+    /// position of `reader`, with lines of at most `max_line_len` bytes.
+    pub fn with_max_line_len(mut reader: BufReader<R>, max_line_len: usize) -> NdjsonReader<R, T> {
+        // room for `\r\n`
+        reader.reserve_max_capacity(max_line_len.saturating_add(2));
+        NdjsonReader {
+            reader,
+            max_line_len,
+            line: 0,
+            done: false,
+            record: PhantomData,
+        }
+    }
+}
+
+impl<R, T> NdjsonReader<R, T> {
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &BufReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut BufReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns the number of lines read so far, including blank lines and
+    /// records that could not be decoded.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Unwraps this `NdjsonReader<R, T>`, returning the underlying reader,
+    /// positioned after the last line read.
+    pub fn into_inner(self) -> BufReader<R> {
+        self.reader
+    }
+}
+
+/// Removes the carriage return of a `\r\n` line ending.
+fn strip_cr(data: &[u8]) -> &[u8] {
+    data.strip_suffix(b"\r").unwrap_or(data)
+}
+
+/// Decodes a line, returning `None` for blank lines.
+fn decode<T: DeserializeOwned>(line: u64, data: &[u8]) -> Option<Result<T, Error<JsonError>>> {
+    if data.iter().all(u8::is_ascii_whitespace) {
+        return None;
+    }
+
+    Some(serde_json::from_slice(data).map_err(|error| Error::Error(JsonError { line, error })))
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for NdjsonReader<R, T> {
+    type Item = Result<T, Error<JsonError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // room for a `\r` before the delimiter
+        let limit = self.max_line_len.saturating_add(1);
+
+        while !self.done {
+            match self.reader.read_until_delim(b"\n", limit) {
+                Ok(data) => {
+                    let data = strip_cr(data);
+                    if data.len() > self.max_line_len {
+                        self.done = true;
+                        return Some(Err(Error::TooLarge));
+                    }
+
+                    self.line += 1;
+                    if let Some(res) = decode(self.line, data) {
+                        return Some(res);
+                    }
+                }
+                Err(Error::Eof) => {
+                    // the last line has no line ending
+                    self.done = true;
+                    let len = self.reader.buffer().len();
+                    if len == 0 {
+                        return None;
+                    }
+                    let data = strip_cr(self.reader.buffer());
+                    if data.len() > self.max_line_len {
+                        return Some(Err(Error::TooLarge));
+                    }
+
+                    self.line += 1;
+                    let res = decode(self.line, data);
+                    self.reader.consume(len);
+                    return res;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<R: fmt::Debug, T> fmt::Debug for NdjsonReader<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NdjsonReader")
+            .field("reader", &self.reader)
+            .field("max_line_len", &self.max_line_len)
+            .field("line", &self.line)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BufReaderBuilder;
+
+    /// A JSON string record taking `len` bytes, with its quotes.
+    fn record(len: usize) -> Vec<u8> {
+        let mut line = vec![b'a'; len];
+        line[0] = b'"';
+        line[len - 1] = b'"';
+        line
+    }
+
+    fn records(data: &[u8], max_line_len: usize) -> NdjsonReader<&[u8], String> {
+        NdjsonReader::with_max_line_len(BufReader::with_capacity(16, data), max_line_len)
+    }
+
+    #[test]
+    fn line_at_the_limit() {
+        for ending in [&b"\n"[..], b"\r\n", b""] {
+            let mut data = record(100);
+            data.extend_from_slice(ending);
+
+            let mut records = records(&data, 100);
+            assert_eq!(records.next().unwrap().unwrap().len(), 98);
+            assert!(records.next().is_none());
+        }
+    }
+
+    #[test]
+    fn line_over_the_limit() {
+        for ending in [&b"\n"[..], b"\r\n", b""] {
+            let mut data = record(101);
+            data.extend_from_slice(ending);
+
+            let mut records = records(&data, 100);
+            assert!(matches!(records.next(), Some(Err(Error::TooLarge))));
+            assert!(records.next().is_none());
+        }
+    }
+
+    #[test]
+    fn grows_the_reader_to_the_limit() {
+        let mut data = record(DEFAULT_MAX_LINE_LEN);
+        data.push(b'\n');
+
+        let reader = BufReader::new(&data[..]);
+        let mut records = NdjsonReader::<_, String>::new(reader);
+        assert_eq!(
+            records.next().unwrap().unwrap().len(),
+            DEFAULT_MAX_LINE_LEN - 2
+        );
+        assert!(records.get_ref().capacity() > DEFAULT_MAX_LINE_LEN);
+        assert_eq!(records.get_ref().max_capacity(), DEFAULT_MAX_LINE_LEN + 2);
+    }
+
+    #[test]
+    fn keeps_a_larger_max_capacity() {
+        let reader = BufReaderBuilder::new()
+            .max_capacity(1024 * 1024)
+            .build(&b""[..]);
+        let records = NdjsonReader::<_, String>::with_max_line_len(reader, 100);
+        assert_eq!(records.get_ref().max_capacity(), 1024 * 1024);
+    }
+
+    #[test]
+    fn blank_lines_and_errors_are_counted() {
+        let mut records = records(b"\"a\"\n\n  \r\n{\n\"b\"", 100);
+        assert_eq!(records.next().unwrap().unwrap(), "a");
+        match records.next() {
+            Some(Err(Error::Error(e))) => assert_eq!(e.line(), 4),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(records.next().unwrap().unwrap(), "b");
+        assert_eq!(records.line(), 5);
+        assert!(records.next().is_none());
+    }
+}
@@ -0,0 +1,100 @@
+//! Buffer pooling
+//!
+//! Every reader allocates its buffer when it is created, and frees it when
+//! it is dropped. Servers handling a lot of short lived connections can
+//! instead share a [`BufferPool`] between their readers, with
+//! [`BufReaderBuilder::pool`](crate::builder::BufReaderBuilder::pool): readers
+//! take their buffer from the pool, and give it back when they are dropped or
+//! unwrapped with `into_inner`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::builder::BufReaderBuilder;
+//! use nom_bufreader::pool::Pool;
+//! use std::sync::Arc;
+//!
+//! let pool = Arc::new(Pool::new(8192, 1000));
+//! let builder = BufReaderBuilder::new().capacity(8192).pool(pool.clone());
+//!
+//! let reader = builder.build(&b"hello"[..]);
+//! assert_eq!(pool.len(), 0);
+//!
+//! drop(reader);
+//! assert_eq!(pool.len(), 1);
+//! ```
+use std::sync::Mutex;
+
+/// A source of buffers for the readers.
+pub trait BufferPool: Send + Sync {
+    /// Returns a buffer that will be used to hold `capacity` bytes.
+    ///
+    /// The buffer does not need to have that capacity already, the reader
+    /// will reserve more space as needed. Its length is considered as the
+    /// part of the buffer that is already initialized, and will not be
+    /// zeroed again: an empty vector with enough capacity is a valid buffer.
+    fn get(&self, capacity: usize) -> Vec<u8>;
+
+    /// Gives back a buffer that is not used anymore.
+    ///
+    /// The buffer can have grown from what `get` returned. Its content is
+    /// left as is and should not be relied upon.
+    fn put(&self, buffer: Vec<u8>);
+}
+
+/// A simple [`BufferPool`] keeping a bounded list of buffers behind a mutex.
+#[derive(Debug)]
+pub struct Pool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    buffer_size: usize,
+    max_buffers: usize,
+}
+
+impl Pool {
+    /// Creates an empty pool, keeping at most `max_buffers` buffers that are
+    /// no larger than `buffer_size`.
+    ///
+    /// Buffers that grew over `buffer_size` are freed instead of being kept,
+    /// so a few large messages do not pin large allocations in the pool.
+    pub fn new(buffer_size: usize, max_buffers: usize) -> Pool {
+        Pool {
+            buffers: Mutex::new(Vec::new()),
+            buffer_size,
+            max_buffers,
+        }
+    }
+
+    /// Returns the number of buffers currently available in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Returns `true` if no buffer is available in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl BufferPool for Pool {
+    fn get(&self, capacity: usize) -> Vec<u8> {
+        if capacity <= self.buffer_size {
+            let buffer = self.buffers.lock().unwrap_or_else(|e| e.into_inner()).pop();
+            if let Some(buffer) = buffer {
+                return buffer;
+            }
+        }
+
+        Vec::with_capacity(capacity)
+    }
+
+    fn put(&self, buffer: Vec<u8>) {
+        if buffer.capacity() > self.buffer_size {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+}
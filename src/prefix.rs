@@ -0,0 +1,289 @@
+//! Length prefixed frames
+//!
+//! A lot of binary protocols send each message as a length followed by a
+//! payload of that length. The readers' `parse_length_prefixed` methods read
+//! the length in one of the [`PrefixKind`] formats, make sure the whole frame
+//! is in the buffer, then apply a parser on exactly the payload.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom::{bytes::complete::take_till, IResult};
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::prefix::PrefixKind;
+//! use nom_bufreader::Error;
+//!
+//! // a name, then a value
+//! fn entry(i: &[u8]) -> IResult<&[u8], (String, Vec<u8>), ()> {
+//!     let (value, name) = take_till(|c| c == b'=')(i)?;
+//!     let name = String::from_utf8_lossy(name).into_owned();
+//!     Ok((&[], (name, value[1..].to_vec())))
+//! }
+//!
+//! fn main() -> Result<(), Error<()>> {
+//!     let mut reader = BufReader::new(&b"\x00\x07a=hello\x00\x40"[..]);
+//!
+//!     let (name, value) = reader.parse_length_prefixed(PrefixKind::U16Be, 16, entry)?;
+//!     assert_eq!(name, "a");
+//!     assert_eq!(value, b"hello");
+//!
+//!     // the next frame is larger than allowed
+//!     let res = reader.parse_length_prefixed(PrefixKind::U16Be, 16, entry);
+//!     assert!(matches!(res, Err(Error::TooLarge)));
+//!     Ok(())
+//! }
+//! ```
+use nom::error::{ErrorKind, ParseError};
+use nom::number::streaming::{be_u16, be_u32, be_u64, be_u8, le_u16, le_u32, le_u64};
+use nom::{Err, IResult, Needed};
+
+/// Encoding of the length before a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrefixKind {
+    U8,
+    U16Be,
+    U16Le,
+    U32Be,
+    U32Le,
+    U64Be,
+    U64Le,
+    /// Unsigned LEB128, as used by Protocol Buffers: 7 bits per byte, least
+    /// significant group first, with the high bit set on all bytes but the
+    /// last one
+    Varint,
+}
+
+impl PrefixKind {
+    /// Parses a length in this format.
+    ///
+    /// This is a streaming parser, returning `Incomplete` if the length is
+    /// not complete. A varint that does not fit in 64 bits is rejected with
+    /// `ErrorKind::TooLarge`.
+    pub fn parse<'a, E: ParseError<&'a [u8]>>(self, i: &'a [u8]) -> IResult<&'a [u8], u64, E> {
+        match self {
+            PrefixKind::U8 => be_u8(i).map(|(i, len)| (i, len.into())),
+            PrefixKind::U16Be => be_u16(i).map(|(i, len)| (i, len.into())),
+            PrefixKind::U16Le => le_u16(i).map(|(i, len)| (i, len.into())),
+            PrefixKind::U32Be => be_u32(i).map(|(i, len)| (i, len.into())),
+            PrefixKind::U32Le => le_u32(i).map(|(i, len)| (i, len.into())),
+            PrefixKind::U64Be => be_u64(i),
+            PrefixKind::U64Le => le_u64(i),
+            PrefixKind::Varint => varint(i),
+        }
+    }
+}
+
+fn varint<'a, E: ParseError<&'a [u8]>>(i: &'a [u8]) -> IResult<&'a [u8], u64, E> {
+    let mut len = 0u64;
+    for (index, byte) in i.iter().enumerate() {
+        let value = u64::from(byte & 0x7f);
+        let shift = 7 * index as u32;
+        if shift >= 64 || (value << shift) >> shift != value {
+            return Err(Err::Error(E::from_error_kind(i, ErrorKind::TooLarge)));
+        }
+
+        len |= value << shift;
+        if byte & 0x80 == 0 {
+            return Ok((&i[index + 1..], len));
+        }
+    }
+    Err(Err::Incomplete(Needed::new(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bufreader::BufReader;
+    use crate::builder::BufReaderBuilder;
+    use crate::Error;
+    use nom::bytes::streaming::take;
+
+    fn prefix(kind: PrefixKind, i: &[u8]) -> IResult<&[u8], u64, ()> {
+        kind.parse(i)
+    }
+
+    fn reader(data: &[u8], max_capacity: usize) -> BufReader<&[u8]> {
+        BufReaderBuilder::new()
+            .capacity(4)
+            .max_capacity(max_capacity)
+            .build(data)
+    }
+
+    fn payload(i: &[u8]) -> IResult<&[u8], Vec<u8>, ()> {
+        Ok((&[], i.to_vec()))
+    }
+
+    /// An error that does not borrow the input.
+    #[derive(Debug)]
+    struct Kind(ErrorKind);
+
+    impl<I> ParseError<I> for Kind {
+        fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+            Kind(kind)
+        }
+
+        fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+            other
+        }
+    }
+
+    fn two_bytes(i: &[u8]) -> IResult<&[u8], Vec<u8>, Kind> {
+        let (i, data) = take(2usize)(i)?;
+        Ok((i, data.to_vec()))
+    }
+
+    #[test]
+    fn fixed_size_prefixes() {
+        assert_eq!(prefix(PrefixKind::U8, &[0xff]), Ok((&[][..], 0xff)));
+        assert_eq!(
+            prefix(PrefixKind::U16Be, &[0x01, 0x02]),
+            Ok((&[][..], 0x0102))
+        );
+        assert_eq!(
+            prefix(PrefixKind::U16Le, &[0x01, 0x02]),
+            Ok((&[][..], 0x0201))
+        );
+        assert_eq!(prefix(PrefixKind::U32Le, &[1, 0, 0, 0]), Ok((&[][..], 1)));
+        assert_eq!(
+            prefix(PrefixKind::U64Be, &[0, 0, 0, 0, 0, 0, 1, 0]),
+            Ok((&[][..], 256))
+        );
+    }
+
+    #[test]
+    fn fixed_size_prefix_incomplete() {
+        assert!(matches!(
+            prefix(PrefixKind::U32Be, &[0, 0, 0]),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn varint() {
+        assert_eq!(
+            prefix(PrefixKind::Varint, &[0x96, 0x01, 0xaa]),
+            Ok((&[0xaa][..], 150))
+        );
+    }
+
+    #[test]
+    fn varint_maximum() {
+        let data = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(prefix(PrefixKind::Varint, &data), Ok((&[][..], u64::MAX)));
+    }
+
+    #[test]
+    fn varint_too_large() {
+        let data = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        assert_eq!(prefix(PrefixKind::Varint, &data), Err(Err::Error(())));
+    }
+
+    #[test]
+    fn varint_incomplete() {
+        assert!(matches!(
+            prefix(PrefixKind::Varint, &[0x80, 0x80]),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn frame_across_refills() {
+        let mut reader = reader(b"\x0aabcdefghij\x01k", 64);
+        let frame = reader
+            .parse_length_prefixed(PrefixKind::U8, 16, payload)
+            .unwrap();
+        assert_eq!(frame, b"abcdefghij");
+        let frame = reader
+            .parse_length_prefixed(PrefixKind::U8, 16, payload)
+            .unwrap();
+        assert_eq!(frame, b"k");
+    }
+
+    #[test]
+    fn frame_of_max_len() {
+        let mut reader = reader(b"\x04abcd", 64);
+        let frame = reader
+            .parse_length_prefixed(PrefixKind::U8, 4, payload)
+            .unwrap();
+        assert_eq!(frame, b"abcd");
+    }
+
+    #[test]
+    fn frame_over_max_len() {
+        let mut reader = reader(b"\x05abcde", 64);
+        let res = reader.parse_length_prefixed(PrefixKind::U8, 4, payload);
+        assert!(matches!(res, Err(Error::TooLarge)));
+        // nothing was consumed
+        let frame = reader
+            .parse_length_prefixed(PrefixKind::U8, 5, payload)
+            .unwrap();
+        assert_eq!(frame, b"abcde");
+    }
+
+    #[test]
+    fn frame_filling_max_capacity() {
+        let mut reader = reader(b"\x07abcdefg", 8);
+        let frame = reader
+            .parse_length_prefixed(PrefixKind::U8, 16, payload)
+            .unwrap();
+        assert_eq!(frame, b"abcdefg");
+    }
+
+    #[test]
+    fn frame_larger_than_max_capacity() {
+        let mut reader = reader(b"\x08abcdefgh", 8);
+        let res = reader.parse_length_prefixed(PrefixKind::U8, 16, payload);
+        assert!(matches!(res, Err(Error::TooLarge)));
+    }
+
+    #[test]
+    fn varint_prefix_too_large() {
+        let mut reader = reader(&[0xff; 16], 64);
+        let res = reader.parse_length_prefixed(PrefixKind::Varint, 16, payload);
+        assert!(matches!(res, Err(Error::TooLarge)));
+    }
+
+    #[test]
+    fn eof_in_prefix() {
+        let mut reader = reader(b"\x00", 64);
+        let res = reader.parse_length_prefixed(PrefixKind::U16Be, 16, payload);
+        assert!(matches!(res, Err(Error::Eof)));
+    }
+
+    #[test]
+    fn eof_in_payload() {
+        let mut reader = reader(b"\x06abc", 64);
+        let res = reader.parse_length_prefixed(PrefixKind::U8, 16, payload);
+        assert!(matches!(res, Err(Error::Eof)));
+    }
+
+    #[test]
+    fn payload_too_short_for_parser() {
+        let mut reader = reader(b"\x01a", 64);
+        let res = reader.parse_length_prefixed(PrefixKind::U8, 16, two_bytes);
+        assert!(matches!(res, Err(Error::Error(Kind(ErrorKind::Complete)))));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_frame_larger_than_max_capacity() {
+        let mut reader = BufReaderBuilder::new()
+            .capacity(4)
+            .max_capacity(8)
+            .build_async(&b"\x08abcdefgh"[..]);
+        let res =
+            futures::executor::block_on(reader.parse_length_prefixed(PrefixKind::U8, 16, payload));
+        assert!(matches!(res, Err(Error::TooLarge)));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_eof_in_payload() {
+        let mut reader = BufReaderBuilder::new()
+            .capacity(4)
+            .build_async(&b"\x06abc"[..]);
+        let res =
+            futures::executor::block_on(reader.parse_length_prefixed(PrefixKind::U8, 16, payload));
+        assert!(matches!(res, Err(Error::Eof)));
+    }
+}
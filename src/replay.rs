@@ -0,0 +1,175 @@
+//! Replaying buffered data after unwrapping a reader
+//!
+//! `into_inner` drops the data that was read in the buffer but not consumed
+//! by a parser. When switching protocols on a connection (like parsing an
+//! HTTP upgrade request, then handing the socket to a WebSocket or TLS
+//! library), that data belongs to the next protocol.
+//! `BufReader::into_inner_with_buffer` returns the underlying reader wrapped
+//! in a [`Replay`], which returns the buffered data first, then reads from
+//! the underlying reader. Writes go directly to the underlying stream.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom::{bytes::streaming::tag, IResult};
+//! use nom_bufreader::bufreader::BufReader;
+//! use nom_bufreader::{Error, Parse};
+//! use std::io::Read;
+//!
+//! fn upgrade(i: &[u8]) -> IResult<&[u8], (), ()> {
+//!     let (i, _) = tag("UPGRADE\r\n")(i)?;
+//!     Ok((i, ()))
+//! }
+//!
+//! fn main() -> Result<(), Error<()>> {
+//!     let mut reader = BufReader::new(&b"UPGRADE\r\nother protocol"[..]);
+//!     reader.parse(upgrade)?;
+//!
+//!     let mut stream = reader.into_inner_with_buffer();
+//!     let mut data = String::new();
+//!     stream.read_to_string(&mut data)?;
+//!     assert_eq!(data, "other protocol");
+//!     Ok(())
+//! }
+//! ```
+use std::cmp;
+use std::fmt;
+use std::io::{self, IoSlice, Read, Write};
+
+#[cfg(feature = "async")]
+use futures::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+
+/// A reader returning some data before reading from the underlying reader,
+/// like [`std::io::Chain`], but also forwarding writes to the underlying
+/// stream.
+pub struct Replay<R> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: R,
+}
+
+impl<R> Replay<R> {
+    /// Creates a reader that will return `prefix` before reading from
+    /// `inner`.
+    pub fn new(prefix: Vec<u8>, inner: R) -> Replay<R> {
+        Replay {
+            prefix,
+            pos: 0,
+            inner,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader, before
+    /// the replayed data was read.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the data that will be returned before reading from the
+    /// underlying reader.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix[self.pos..]
+    }
+
+    /// Unwraps this `Replay<R>`, returning the underlying reader and the data
+    /// that was not replayed yet.
+    pub fn into_parts(mut self) -> (R, Vec<u8>) {
+        self.prefix.drain(..self.pos);
+        (self.inner, self.prefix)
+    }
+
+    /// Copies replayed data to `buf`, freeing the prefix once it was
+    /// completely read.
+    fn read_prefix(&mut self, buf: &mut [u8]) -> usize {
+        let n = cmp::min(buf.len(), self.prefix.len() - self.pos);
+        buf[..n].copy_from_slice(&self.prefix[self.pos..self.pos + n]);
+        self.pos += n;
+        if self.pos == self.prefix.len() {
+            self.prefix = Vec::new();
+            self.pos = 0;
+        }
+        n
+    }
+}
+
+impl<R: Read> Read for Replay<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.prefix.len() {
+            return Ok(self.read_prefix(buf));
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Write> Write for Replay<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncRead for Replay<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pos < this.prefix.len() {
+            return Poll::Ready(Ok(this.read_prefix(buf)));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncWrite + Unpin> AsyncWrite for Replay<R> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for Replay<R> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Replay")
+            .field("reader", &self.inner)
+            .field("prefix", &(self.prefix.len() - self.pos))
+            .finish()
+    }
+}
@@ -0,0 +1,617 @@
+//! Redis serialization protocol (RESP2 and RESP3)
+//!
+//! [RESP](https://redis.io/docs/latest/develop/reference/protocol-spec/) is
+//! the protocol spoken by Redis clients and servers. It is a good example of
+//! a streaming protocol: values are nested, and their size is only known as
+//! they are parsed.
+//!
+//! [`value`] is a nom parser for a whole value, usable with `Parse` and
+//! `AsyncParse` when values are known to fit in the buffer. [`read_value`]
+//! reads values of any size from an `async_bufreader::BufReader`: it parses
+//! the type and length of each element with nom, and reads the payload of
+//! bulk strings directly, so they can be larger than the buffer. Nested
+//! aggregates are handled without recursion. [`Value::encode`] serializes
+//! values, for the other direction.
+//!
+//! See `examples/resp_server.rs` for a client and a server talking to each
+//! other.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use nom_bufreader::async_bufreader::BufReader;
+//! use nom_bufreader::resp::{read_value, Value};
+//!
+//! # futures::executor::block_on(async {
+//! let mut reader = BufReader::with_capacity(
+//!     8,
+//!     &b"*2\r\n$16\r\nlarger than 8 B!\r\n%1\r\n+key\r\n:42\r\n"[..],
+//! );
+//!
+//! let value = read_value(&mut reader).await.unwrap();
+//! assert_eq!(
+//!     value,
+//!     Value::Array(vec![
+//!         Value::BulkString(b"larger than 8 B!".to_vec()),
+//!         Value::Map(vec![(Value::SimpleString("key".into()), Value::Integer(42))]),
+//!     ])
+//! );
+//! # });
+//! ```
+use super::async_bufreader::BufReader;
+use super::{AsyncParse, Error};
+use futures::io::AsyncRead;
+use nom::bytes::streaming::{tag, take, take_until};
+use nom::error::{ErrorKind, ParseError};
+use nom::{Err, IResult};
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::str;
+
+/// Maximum nesting of aggregates in a value.
+pub const MAX_DEPTH: usize = 128;
+
+/// Maximum length of a bulk string, like the default `proto-max-bulk-len`
+/// of Redis.
+pub const MAX_BULK_LEN: u64 = 512 * 1024 * 1024;
+
+/// A RESP value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    SimpleString(String),
+    /// A simple error, like `ERR unknown command`
+    Error(String),
+    Integer(i64),
+    BulkString(Vec<u8>),
+    Array(Vec<Value>),
+    /// The RESP3 null, and the RESP2 null bulk string and null array
+    Null,
+    Boolean(bool),
+    Double(f64),
+    /// An integer of any size, as its decimal representation
+    BigNumber(String),
+    BulkError(Vec<u8>),
+    /// A string with a three characters format, like `txt` or `mkd`
+    VerbatimString {
+        format: String,
+        data: Vec<u8>,
+    },
+    Map(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+    /// Out of band data pushed by the server
+    Push(Vec<Value>),
+    /// A value preceded by attributes
+    Attribute {
+        attributes: Vec<(Value, Value)>,
+        value: Box<Value>,
+    },
+}
+
+/// Errors returned by the RESP parsers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RespError {
+    /// The input does not follow the RESP grammar
+    Syntax(ErrorKind),
+    /// Unknown type byte
+    InvalidType(u8),
+    InvalidInteger,
+    InvalidLength,
+    /// A simple string or error that is not valid UTF-8
+    InvalidString,
+    /// Invalid null, boolean, double, big number or verbatim string
+    InvalidValue,
+    /// A bulk string is larger than [`MAX_BULK_LEN`]
+    TooLarge,
+    /// Aggregates are nested deeper than [`MAX_DEPTH`]
+    TooDeep,
+}
+
+impl<I> ParseError<I> for RespError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        RespError::Syntax(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl fmt::Display for RespError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RespError::Syntax(kind) => write!(f, "invalid RESP syntax ({:?})", kind),
+            RespError::InvalidType(t) => write!(f, "invalid RESP type {:?}", *t as char),
+            RespError::InvalidInteger => f.write_str("invalid integer"),
+            RespError::InvalidLength => f.write_str("invalid length"),
+            RespError::InvalidString => f.write_str("invalid UTF-8 string"),
+            RespError::InvalidValue => f.write_str("invalid value"),
+            RespError::TooLarge => f.write_str("bulk string too large"),
+            RespError::TooDeep => f.write_str("aggregates nested too deep"),
+        }
+    }
+}
+
+impl std::error::Error for RespError {}
+
+fn failure<O>(e: RespError) -> Result<O, Err<RespError>> {
+    Err(Err::Failure(e))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BlobKind {
+    BulkString,
+    BulkError,
+    Verbatim,
+}
+
+impl BlobKind {
+    fn value(self, mut data: Vec<u8>) -> Result<Value, RespError> {
+        Ok(match self {
+            BlobKind::BulkString => Value::BulkString(data),
+            BlobKind::BulkError => Value::BulkError(data),
+            BlobKind::Verbatim => {
+                if data.len() < 4 || data[3] != b':' {
+                    return Err(RespError::InvalidValue);
+                }
+                let format = str::from_utf8(&data[..3])
+                    .map_err(|_| RespError::InvalidString)?
+                    .to_string();
+                data.drain(..4);
+                Value::VerbatimString { format, data }
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AggregateKind {
+    Array,
+    Set,
+    Push,
+    Map,
+    Attribute,
+}
+
+impl AggregateKind {
+    /// Number of values following the header, for `len` declared elements.
+    fn count(self, len: u64) -> u64 {
+        match self {
+            AggregateKind::Array | AggregateKind::Set | AggregateKind::Push => len,
+            AggregateKind::Map => len.saturating_mul(2),
+            // the attributes, then the value they apply to
+            AggregateKind::Attribute => len.saturating_mul(2).saturating_add(1),
+        }
+    }
+
+    fn value(self, mut items: Vec<Value>) -> Value {
+        match self {
+            AggregateKind::Array => Value::Array(items),
+            AggregateKind::Set => Value::Set(items),
+            AggregateKind::Push => Value::Push(items),
+            AggregateKind::Map => Value::Map(pairs(items)),
+            AggregateKind::Attribute => {
+                let value = items.pop().unwrap_or(Value::Null);
+                Value::Attribute {
+                    attributes: pairs(items),
+                    value: Box::new(value),
+                }
+            }
+        }
+    }
+}
+
+fn pairs(items: Vec<Value>) -> Vec<(Value, Value)> {
+    let mut pairs = Vec::with_capacity(items.len() / 2);
+    let mut items = items.into_iter();
+    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+        pairs.push((key, value));
+    }
+    pairs
+}
+
+/// The first line of a value.
+#[derive(Clone, Debug, PartialEq)]
+enum Header {
+    /// A value that holds on a single line
+    Value(Value),
+    /// A value with a payload of the given length on the next lines
+    Blob(BlobKind, u64),
+    /// An aggregate of the given number of elements
+    Aggregate(AggregateKind, u64),
+}
+
+fn crlf(i: &[u8]) -> IResult<&[u8], (), RespError> {
+    let (i, _) = tag("\r\n")(i)?;
+    Ok((i, ()))
+}
+
+fn line(i: &[u8]) -> IResult<&[u8], &[u8], RespError> {
+    let (i, line) = take_until("\r\n")(i)?;
+    Ok((&i[2..], line))
+}
+
+fn string(s: &[u8]) -> Result<String, Err<RespError>> {
+    match str::from_utf8(s) {
+        Ok(s) => Ok(s.to_string()),
+        Err(_) => failure(RespError::InvalidString),
+    }
+}
+
+fn integer(s: &[u8]) -> Result<i64, Err<RespError>> {
+    match str::from_utf8(s).ok().and_then(|s| s.parse().ok()) {
+        Some(n) => Ok(n),
+        None => failure(RespError::InvalidInteger),
+    }
+}
+
+/// Parses a length, `-1` standing for null when `nullable` is set.
+fn length(s: &[u8], nullable: bool) -> Result<Option<u64>, Err<RespError>> {
+    match integer(s) {
+        Ok(-1) if nullable => Ok(None),
+        Ok(n) if n >= 0 => Ok(Some(n as u64)),
+        _ => failure(RespError::InvalidLength),
+    }
+}
+
+fn blob(kind: BlobKind, s: &[u8], nullable: bool) -> Result<Header, Err<RespError>> {
+    match length(s, nullable)? {
+        None => Ok(Header::Value(Value::Null)),
+        Some(len) if len > MAX_BULK_LEN => failure(RespError::TooLarge),
+        Some(len) => Ok(Header::Blob(kind, len)),
+    }
+}
+
+fn aggregate(kind: AggregateKind, s: &[u8], nullable: bool) -> Result<Header, Err<RespError>> {
+    match length(s, nullable)? {
+        None => Ok(Header::Value(Value::Null)),
+        Some(len) => Ok(Header::Aggregate(kind, len)),
+    }
+}
+
+fn header(i: &[u8]) -> IResult<&[u8], Header, RespError> {
+    let (i, kind) = take(1usize)(i)?;
+    let (i, line) = line(i)?;
+
+    let header = match kind[0] {
+        b'+' => Header::Value(Value::SimpleString(string(line)?)),
+        b'-' => Header::Value(Value::Error(string(line)?)),
+        b':' => Header::Value(Value::Integer(integer(line)?)),
+        b'_' if line.is_empty() => Header::Value(Value::Null),
+        b'#' => match line {
+            b"t" => Header::Value(Value::Boolean(true)),
+            b"f" => Header::Value(Value::Boolean(false)),
+            _ => return failure(RespError::InvalidValue),
+        },
+        b',' => match str::from_utf8(line).ok().and_then(|s| s.parse().ok()) {
+            Some(d) => Header::Value(Value::Double(d)),
+            None => return failure(RespError::InvalidValue),
+        },
+        b'(' => {
+            let digits = line.strip_prefix(b"-").unwrap_or(line);
+            if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                return failure(RespError::InvalidValue);
+            }
+            Header::Value(Value::BigNumber(string(line)?))
+        }
+        b'$' => blob(BlobKind::BulkString, line, true)?,
+        b'!' => blob(BlobKind::BulkError, line, false)?,
+        b'=' => blob(BlobKind::Verbatim, line, false)?,
+        b'*' => aggregate(AggregateKind::Array, line, true)?,
+        b'~' => aggregate(AggregateKind::Set, line, false)?,
+        b'>' => aggregate(AggregateKind::Push, line, false)?,
+        b'%' => aggregate(AggregateKind::Map, line, false)?,
+        b'|' => aggregate(AggregateKind::Attribute, line, false)?,
+        b'_' => return failure(RespError::InvalidValue),
+        t => return failure(RespError::InvalidType(t)),
+    };
+    Ok((i, header))
+}
+
+/// Parses a complete value.
+///
+/// This needs the whole value in the buffer: use [`read_value`] for values
+/// that can be larger.
+pub fn value(i: &[u8]) -> IResult<&[u8], Value, RespError> {
+    value_at_depth(i, 0)
+}
+
+fn value_at_depth(i: &[u8], depth: usize) -> IResult<&[u8], Value, RespError> {
+    let (mut i, header) = header(i)?;
+    match header {
+        Header::Value(value) => Ok((i, value)),
+        Header::Blob(kind, len) => {
+            let (i, data) = take(len)(i)?;
+            let (i, _) = crlf(i)?;
+            Ok((i, kind.value(data.to_vec()).map_err(Err::Failure)?))
+        }
+        Header::Aggregate(kind, len) => {
+            if depth == MAX_DEPTH {
+                return failure(RespError::TooDeep);
+            }
+
+            let count = kind.count(len);
+            // the length comes from the input, do not trust it to allocate
+            let mut items = Vec::with_capacity(cmp::min(count, 64) as usize);
+            for _ in 0..count {
+                let (rest, value) = value_at_depth(i, depth + 1)?;
+                items.push(value);
+                i = rest;
+            }
+            Ok((i, kind.value(items)))
+        }
+    }
+}
+
+/// Parses the header of an element. When its line does not fit in the
+/// buffer, filling it fails with an error of kind `Interrupted`, that would
+/// look like a transient error to the caller: it is returned as
+/// `Error::TooLarge` instead.
+async fn read_header<R>(reader: &mut BufReader<R>) -> Result<Header, Error<RespError>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    match reader.parse(header).await {
+        Err(Error::Io(e))
+            if e.kind() == io::ErrorKind::Interrupted
+                && reader.buffer().len() == reader.max_capacity() =>
+        {
+            Err(Error::TooLarge)
+        }
+        res => res,
+    }
+}
+
+/// An aggregate being read by `read_value`.
+struct Pending {
+    kind: AggregateKind,
+    remaining: u64,
+    items: Vec<Value>,
+}
+
+/// Reads a value of any size from `reader`.
+///
+/// Only the type and length of each element need to fit in the buffer: the
+/// payload of bulk strings is read directly from the underlying reader if
+/// needed. A line that does not fit in the maximum capacity of the buffer,
+/// like a very long simple string, is rejected with `Error::TooLarge`. If
+/// the stream ends in the middle of a value, this returns `Error::Eof`.
+pub async fn read_value<R>(reader: &mut BufReader<R>) -> Result<Value, Error<RespError>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut stack: Vec<Pending> = Vec::new();
+    loop {
+        let mut value = match read_header(reader).await? {
+            Header::Value(value) => value,
+            Header::Blob(kind, len) => {
+                let mut data = Vec::new();
+                reader.parse_take(len, &mut data).await?;
+                reader.parse(crlf).await?;
+                kind.value(data).map_err(Error::Failure)?
+            }
+            Header::Aggregate(kind, len) => {
+                let count = kind.count(len);
+                if count > 0 {
+                    if stack.len() == MAX_DEPTH {
+                        return Err(Error::Failure(RespError::TooDeep));
+                    }
+                    stack.push(Pending {
+                        kind,
+                        remaining: count,
+                        items: Vec::with_capacity(cmp::min(count, 64) as usize),
+                    });
+                    continue;
+                }
+                kind.value(Vec::new())
+            }
+        };
+
+        // add the value to its parent, and the completed parents to theirs
+        loop {
+            let parent = match stack.last_mut() {
+                None => return Ok(value),
+                Some(parent) => parent,
+            };
+            parent.items.push(value);
+            parent.remaining -= 1;
+            if parent.remaining > 0 {
+                break;
+            }
+
+            let parent = stack.pop().expect("the stack is not empty");
+            value = parent.kind.value(parent.items);
+        }
+    }
+}
+
+impl Value {
+    /// Serializes the value, appending it to `dst`.
+    ///
+    /// [`Value::Null`] is written as a RESP2 null bulk string, that RESP2
+    /// and RESP3 clients both understand.
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        match self {
+            Value::SimpleString(s) => encode_line(dst, b'+', s.as_bytes()),
+            Value::Error(s) => encode_line(dst, b'-', s.as_bytes()),
+            Value::Integer(n) => encode_line(dst, b':', n.to_string().as_bytes()),
+            Value::BulkString(data) => encode_blob(dst, b'$', &[], data),
+            Value::Array(items) => encode_items(dst, b'*', items),
+            Value::Null => dst.extend_from_slice(b"$-1\r\n"),
+            Value::Boolean(b) => encode_line(dst, b'#', if *b { b"t" } else { b"f" }),
+            Value::Double(d) => {
+                let s = if d.is_nan() {
+                    "nan".to_string()
+                } else {
+                    d.to_string()
+                };
+                encode_line(dst, b',', s.as_bytes())
+            }
+            Value::BigNumber(s) => encode_line(dst, b'(', s.as_bytes()),
+            Value::BulkError(data) => encode_blob(dst, b'!', &[], data),
+            Value::VerbatimString { format, data } => {
+                let mut prefix = format.as_bytes().to_vec();
+                prefix.push(b':');
+                encode_blob(dst, b'=', &prefix, data)
+            }
+            Value::Map(pairs) => encode_pairs(dst, b'%', pairs),
+            Value::Set(items) => encode_items(dst, b'~', items),
+            Value::Push(items) => encode_items(dst, b'>', items),
+            Value::Attribute { attributes, value } => {
+                encode_pairs(dst, b'|', attributes);
+                value.encode(dst);
+            }
+        }
+    }
+}
+
+fn encode_line(dst: &mut Vec<u8>, kind: u8, line: &[u8]) {
+    dst.push(kind);
+    dst.extend_from_slice(line);
+    dst.extend_from_slice(b"\r\n");
+}
+
+fn encode_blob(dst: &mut Vec<u8>, kind: u8, prefix: &[u8], data: &[u8]) {
+    let len = prefix.len() + data.len();
+    encode_line(dst, kind, len.to_string().as_bytes());
+    dst.extend_from_slice(prefix);
+    dst.extend_from_slice(data);
+    dst.extend_from_slice(b"\r\n");
+}
+
+fn encode_items(dst: &mut Vec<u8>, kind: u8, items: &[Value]) {
+    encode_line(dst, kind, items.len().to_string().as_bytes());
+    for item in items {
+        item.encode(dst);
+    }
+}
+
+fn encode_pairs(dst: &mut Vec<u8>, kind: u8, pairs: &[(Value, Value)]) {
+    encode_line(dst, kind, pairs.len().to_string().as_bytes());
+    for (key, value) in pairs {
+        key.encode(dst);
+        value.encode(dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BufReaderBuilder;
+    use futures::executor::block_on;
+
+    fn value_error(i: &[u8]) -> RespError {
+        match value(i) {
+            Err(Err::Failure(e)) => e,
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    fn read(data: &[u8], max_capacity: usize) -> Result<Value, Error<RespError>> {
+        let mut reader = BufReaderBuilder::new()
+            .capacity(8)
+            .max_capacity(max_capacity)
+            .build_async(data);
+        block_on(read_value(&mut reader))
+    }
+
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut data = b"*1\r\n".repeat(depth);
+        data.extend_from_slice(b":1\r\n");
+        data
+    }
+
+    #[test]
+    fn bulk_string_of_max_len() {
+        let header = format!("${}\r\n", MAX_BULK_LEN);
+        assert!(matches!(value(header.as_bytes()), Err(Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn bulk_string_over_max_len() {
+        let header = format!("${}\r\n", MAX_BULK_LEN + 1);
+        assert_eq!(value_error(header.as_bytes()), RespError::TooLarge);
+    }
+
+    #[test]
+    fn negative_length() {
+        assert_eq!(value_error(b"$-2\r\n"), RespError::InvalidLength);
+    }
+
+    #[test]
+    fn null_bulk_error() {
+        assert_eq!(value_error(b"!-1\r\n"), RespError::InvalidLength);
+    }
+
+    #[test]
+    fn max_depth() {
+        assert!(value(&nested(MAX_DEPTH)).is_ok());
+    }
+
+    #[test]
+    fn too_deep() {
+        assert_eq!(value_error(&nested(MAX_DEPTH + 1)), RespError::TooDeep);
+    }
+
+    #[test]
+    fn read_max_depth() {
+        assert!(read(&nested(MAX_DEPTH), 64).is_ok());
+    }
+
+    #[test]
+    fn read_too_deep() {
+        let res = read(&nested(MAX_DEPTH + 1), 64);
+        assert!(matches!(res, Err(Error::Failure(RespError::TooDeep))));
+    }
+
+    #[test]
+    fn read_bulk_string_larger_than_buffer() {
+        let mut data = b"$32\r\n".to_vec();
+        data.extend_from_slice(&[b'a'; 32]);
+        data.extend_from_slice(b"\r\n");
+        assert_eq!(read(&data, 16).unwrap(), Value::BulkString(vec![b'a'; 32]));
+    }
+
+    #[test]
+    fn read_bulk_string_over_max_len() {
+        let data = format!("${}\r\n", MAX_BULK_LEN + 1);
+        let res = read(data.as_bytes(), 64);
+        assert!(matches!(res, Err(Error::Failure(RespError::TooLarge))));
+    }
+
+    #[test]
+    fn read_line_filling_max_capacity() {
+        let data = b"+abcdefghijklm\r\n";
+        assert_eq!(data.len(), 16);
+        assert_eq!(
+            read(data, 16).unwrap(),
+            Value::SimpleString("abcdefghijklm".into())
+        );
+    }
+
+    #[test]
+    fn read_line_longer_than_max_capacity() {
+        let res = read(b"+abcdefghijklmn\r\n", 16);
+        assert!(matches!(res, Err(Error::TooLarge)));
+    }
+
+    #[test]
+    fn read_eof_in_header() {
+        assert!(matches!(read(b"+abc", 64), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn read_eof_in_bulk_string() {
+        assert!(matches!(read(b"$10\r\nabc", 64), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn read_eof_before_crlf() {
+        assert!(matches!(read(b"$3\r\nabc", 64), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn read_eof_in_aggregate() {
+        assert!(matches!(read(b"*2\r\n:1\r\n", 64), Err(Error::Eof)));
+    }
+}
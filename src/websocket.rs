@@ -0,0 +1,780 @@
+//! WebSocket frames and messages, as described in
+//! [RFC 6455](https://www.rfc-editor.org/rfc/rfc6455)
+//!
+//! Once a connection was upgraded, the same `async_bufreader::BufReader`
+//! that parsed the HTTP head keeps reading, with the data that followed the
+//! head still in its buffer. [`WebSocketReader`] wraps it, parsing frame
+//! headers with nom, then reading payloads, that can be larger than the
+//! buffer.
+//!
+//! Payloads are copied out of the reader's buffer once, into the `Vec` of
+//! the frame or message they belong to, and unmasked there. They cannot be
+//! unmasked in the buffer and returned from it: a payload larger than the
+//! buffer is read over several refills, the fragments of a message are
+//! concatenated, and the buffer is reused for the next frames while the
+//! returned message is still alive.
+//!
+//! [`WebSocketReader::read_frame`] returns frames one by one, and
+//! [`WebSocketReader::read_message`] reassembles fragmented messages.
+//! Control frames can come between the fragments of a message: they are
+//! returned as soon as they are read, and the message is completed by the
+//! next calls.
+//!
+//! Masking is not enforced: a server should check [`FrameHeader::mask`] and
+//! close the connection if a client sent an unmasked frame. Extensions are
+//! not supported, so frames with reserved bits set are rejected.
+//!
+//! # Examples
+//!
+//! The examples of section 5.7 of the RFC:
+//!
+//! ```rust
+//! use nom_bufreader::async_bufreader::BufReader;
+//! use nom_bufreader::websocket::{Message, Opcode, WebSocketReader};
+//!
+//! let mut data = vec![
+//!     // a single-frame unmasked text message
+//!     0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+//!     // a single-frame masked text message
+//!     0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+//!     // the first fragment of an unmasked text message
+//!     0x01, 0x03, 0x48, 0x65, 0x6c,
+//!     // an unmasked ping, between fragments
+//!     0x89, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+//!     // the last fragment
+//!     0x80, 0x02, 0x6c, 0x6f,
+//!     // a masked pong
+//!     0x8a, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+//!     // 256 bytes binary message in a single unmasked frame
+//!     0x82, 0x7e, 0x01, 0x00,
+//! ];
+//! data.extend_from_slice(&[0xab; 256]);
+//! // 64KiB binary message in a single unmasked frame
+//! data.extend_from_slice(&[0x82, 0x7f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00]);
+//! data.extend_from_slice(&[0xcd; 65536]);
+//!
+//! # futures::executor::block_on(async {
+//! let mut ws = WebSocketReader::new(BufReader::with_capacity(64, &data[..]));
+//!
+//! assert_eq!(ws.read_message().await.unwrap(), Message::Text("Hello".into()));
+//!
+//! let frame = ws.read_frame().await.unwrap();
+//! assert_eq!(frame.header.opcode, Opcode::Text);
+//! assert_eq!(frame.header.mask, Some([0x37, 0xfa, 0x21, 0x3d]));
+//! assert_eq!(frame.payload, b"Hello");
+//!
+//! assert_eq!(ws.read_message().await.unwrap(), Message::Ping(b"Hello".to_vec()));
+//! assert_eq!(ws.read_message().await.unwrap(), Message::Text("Hello".into()));
+//! assert_eq!(ws.read_message().await.unwrap(), Message::Pong(b"Hello".to_vec()));
+//! assert_eq!(ws.read_message().await.unwrap(), Message::Binary(vec![0xab; 256]));
+//! assert_eq!(ws.read_message().await.unwrap(), Message::Binary(vec![0xcd; 65536]));
+//! # });
+//! ```
+use super::async_bufreader::BufReader;
+use super::{AsyncParse, Error};
+use futures::io::AsyncRead;
+use nom::bytes::streaming::take;
+use nom::error::{ErrorKind, ParseError};
+use nom::number::streaming::{be_u16, be_u64, be_u8};
+use nom::{Err, IResult};
+use std::fmt;
+use std::str;
+
+/// Default maximum length of a message, reassembled from its fragments.
+pub const DEFAULT_MAX_MESSAGE_LEN: u64 = 16 * 1024 * 1024;
+
+/// Maximum payload length of control frames.
+const MAX_CONTROL_LEN: u64 = 125;
+
+/// Errors returned by the WebSocket parsers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebSocketError {
+    /// The input does not follow the frame grammar
+    Syntax(ErrorKind),
+    /// Reserved bits are set, without a negotiated extension
+    ReservedBits,
+    /// Reserved opcode
+    InvalidOpcode(u8),
+    /// A 64 bits payload length with the most significant bit set, or an
+    /// extended length that does not use the shortest encoding
+    InvalidLength,
+    /// A fragmented control frame, or one with a payload longer than 125
+    /// bytes
+    InvalidControlFrame,
+    /// A continuation frame outside of a fragmented message
+    UnexpectedContinuation,
+    /// A new message started before the end of a fragmented message
+    ExpectedContinuation,
+    /// A text message, or close reason, that is not valid UTF-8
+    InvalidUtf8,
+    /// A close frame with a one byte payload
+    InvalidClosePayload,
+    /// A status code that cannot be sent in a close frame
+    InvalidCloseCode(u16),
+}
+
+impl<I> ParseError<I> for WebSocketError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        WebSocketError::Syntax(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebSocketError::Syntax(kind) => write!(f, "invalid frame syntax ({:?})", kind),
+            WebSocketError::ReservedBits => f.write_str("reserved bits set"),
+            WebSocketError::InvalidOpcode(op) => write!(f, "invalid opcode {:#x}", op),
+            WebSocketError::InvalidLength => f.write_str("invalid payload length"),
+            WebSocketError::InvalidControlFrame => f.write_str("invalid control frame"),
+            WebSocketError::UnexpectedContinuation => f.write_str("unexpected continuation frame"),
+            WebSocketError::ExpectedContinuation => f.write_str("expected a continuation frame"),
+            WebSocketError::InvalidUtf8 => f.write_str("invalid UTF-8 text"),
+            WebSocketError::InvalidClosePayload => f.write_str("invalid close frame payload"),
+            WebSocketError::InvalidCloseCode(code) => write!(f, "invalid close code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {}
+
+fn failure<O>(e: WebSocketError) -> Result<O, Err<WebSocketError>> {
+    Err(Err::Failure(e))
+}
+
+/// The type of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    /// Returns the opcode for the low 4 bits of the first byte of a frame,
+    /// or `None` if it is reserved.
+    pub fn from_u8(op: u8) -> Option<Opcode> {
+        Some(match op {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xa => Opcode::Pong,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` for close, ping and pong frames.
+    pub fn is_control(self) -> bool {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+/// The header of a frame, before its payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Set on the last frame of a message
+    pub fin: bool,
+    pub opcode: Opcode,
+    /// The masking key, for frames sent by a client
+    pub mask: Option<[u8; 4]>,
+    pub payload_len: u64,
+}
+
+/// Parses a frame header.
+///
+/// Frames with reserved bits or opcodes are rejected, as well as control
+/// frames that are fragmented or too long, and extended lengths that do not
+/// use the shortest encoding.
+///
+/// # Examples
+///
+/// ```rust
+/// use nom::Err;
+/// use nom_bufreader::websocket::{frame_header, Opcode, WebSocketError};
+///
+/// // a masked text frame of 5 bytes, followed by its payload
+/// let (payload, header) = frame_header(b"\x81\x85\x37\xfa\x21\x3dpayload").unwrap();
+/// assert_eq!(header.opcode, Opcode::Text);
+/// assert_eq!(header.mask, Some([0x37, 0xfa, 0x21, 0x3d]));
+/// assert_eq!(header.payload_len, 5);
+/// assert_eq!(payload, b"payload");
+///
+/// // a ping without FIN
+/// assert_eq!(
+///     frame_header(&[0x09, 0x00]),
+///     Err(Err::Failure(WebSocketError::InvalidControlFrame))
+/// );
+/// ```
+pub fn frame_header(i: &[u8]) -> IResult<&[u8], FrameHeader, WebSocketError> {
+    let (i, first) = be_u8(i)?;
+    let (i, second) = be_u8(i)?;
+
+    let fin = first & 0x80 != 0;
+    if first & 0x70 != 0 {
+        return failure(WebSocketError::ReservedBits);
+    }
+    let opcode = match Opcode::from_u8(first & 0x0f) {
+        Some(opcode) => opcode,
+        None => return failure(WebSocketError::InvalidOpcode(first & 0x0f)),
+    };
+
+    let (i, payload_len) = match second & 0x7f {
+        // extended lengths must use the shortest encoding
+        126 => {
+            let (i, len) = be_u16(i)?;
+            if len < 126 {
+                return failure(WebSocketError::InvalidLength);
+            }
+            (i, u64::from(len))
+        }
+        127 => {
+            let (i, len) = be_u64(i)?;
+            if len >> 63 != 0 || len <= u64::from(u16::MAX) {
+                return failure(WebSocketError::InvalidLength);
+            }
+            (i, len)
+        }
+        len => (i, u64::from(len)),
+    };
+
+    if opcode.is_control() && (!fin || payload_len > MAX_CONTROL_LEN) {
+        return failure(WebSocketError::InvalidControlFrame);
+    }
+
+    let (i, mask) = if second & 0x80 != 0 {
+        let (i, key) = take(4usize)(i)?;
+        (i, Some([key[0], key[1], key[2], key[3]]))
+    } else {
+        (i, None)
+    };
+
+    Ok((
+        i,
+        FrameHeader {
+            fin,
+            opcode,
+            mask,
+            payload_len,
+        },
+    ))
+}
+
+/// Masks or unmasks `data` in place with the masking key of a frame.
+///
+/// `data` must start at the beginning of the payload.
+pub fn apply_mask(mask: [u8; 4], data: &mut [u8]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// A frame, with its unmasked payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub header: FrameHeader,
+    pub payload: Vec<u8>,
+}
+
+/// A message, reassembled from its fragments, or a control frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// A close frame, with its status code and reason, if any
+    Close(Option<(u16, String)>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+}
+
+/// Returns `true` for the status codes that can be sent in a close frame:
+/// the ones defined by the RFC and the IANA registry, and the ranges left to
+/// libraries and applications (section 7.4).
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+impl Message {
+    /// Builds a message from its opcode and complete payload.
+    fn from_payload(opcode: Opcode, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+        Ok(match opcode {
+            Opcode::Text => {
+                Message::Text(String::from_utf8(payload).map_err(|_| WebSocketError::InvalidUtf8)?)
+            }
+            Opcode::Binary => Message::Binary(payload),
+            Opcode::Close => match payload.len() {
+                0 => Message::Close(None),
+                1 => return Err(WebSocketError::InvalidClosePayload),
+                _ => {
+                    let code = u16::from_be_bytes([payload[0], payload[1]]);
+                    if !is_valid_close_code(code) {
+                        return Err(WebSocketError::InvalidCloseCode(code));
+                    }
+                    let reason =
+                        str::from_utf8(&payload[2..]).map_err(|_| WebSocketError::InvalidUtf8)?;
+                    Message::Close(Some((code, reason.to_string())))
+                }
+            },
+            Opcode::Ping => Message::Ping(payload),
+            Opcode::Pong => Message::Pong(payload),
+            Opcode::Continuation => return Err(WebSocketError::UnexpectedContinuation),
+        })
+    }
+}
+
+/// Reads WebSocket frames and messages from an [`async_bufreader::BufReader`](BufReader).
+///
+/// Payloads and messages larger than the maximum message length are
+/// rejected with `Error::TooLarge` before they are read. After an error, the
+/// connection should be closed.
+pub struct WebSocketReader<R> {
+    reader: BufReader<R>,
+    max_message_len: u64,
+    /// The opcode and data of a fragmented message being reassembled
+    fragments: Option<(Opcode, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin + Send> WebSocketReader<R> {
+    /// Creates a reader for the frames starting at the current position of
+    /// `reader`, with messages of at most [`DEFAULT_MAX_MESSAGE_LEN`] bytes.
+    pub fn new(reader: BufReader<R>) -> WebSocketReader<R> {
+        Self::with_max_message_len(reader, DEFAULT_MAX_MESSAGE_LEN)
+    }
+
+    /// Creates a reader for the frames starting at the current position of
+    /// `reader`, with messages of at most `max_message_len` bytes.
+    pub fn with_max_message_len(reader: BufReader<R>, max_message_len: u64) -> WebSocketReader<R> {
+        WebSocketReader {
+            reader,
+            max_message_len,
+            fragments: None,
+        }
+    }
+
+    /// Reads the next frame, and unmasks its payload.
+    ///
+    /// This does not check the sequence of frames: it should not be mixed
+    /// with [`read_message`](WebSocketReader::read_message) in the middle of
+    /// a fragmented message.
+    pub async fn read_frame(&mut self) -> Result<Frame, Error<WebSocketError>> {
+        let header = self.reader.parse(frame_header).await?;
+        if header.payload_len > self.max_message_len {
+            return Err(Error::TooLarge);
+        }
+
+        let mut payload = Vec::new();
+        read_payload(&mut self.reader, &header, &mut payload).await?;
+        Ok(Frame { header, payload })
+    }
+
+    /// Reads the next message, reassembling its fragments, or the next
+    /// control frame.
+    ///
+    /// The fragments must follow the rules of section 5.4 of the RFC, and
+    /// the payloads of text messages and close frames are validated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use nom_bufreader::async_bufreader::BufReader;
+    /// use nom_bufreader::websocket::{Message, WebSocketError, WebSocketReader};
+    /// use nom_bufreader::Error;
+    ///
+    /// # futures::executor::block_on(async {
+    /// // a close frame with the status code 1000 and a reason
+    /// let data = b"\x88\x06\x03\xe8gone";
+    /// let mut ws = WebSocketReader::new(BufReader::new(&data[..]));
+    /// assert_eq!(
+    ///     ws.read_message().await.unwrap(),
+    ///     Message::Close(Some((1000, "gone".to_string())))
+    /// );
+    ///
+    /// // the reserved status code 1005
+    /// let mut ws = WebSocketReader::new(BufReader::new(&b"\x88\x02\x03\xed"[..]));
+    /// assert!(matches!(
+    ///     ws.read_message().await,
+    ///     Err(Error::Failure(WebSocketError::InvalidCloseCode(1005)))
+    /// ));
+    /// # });
+    /// ```
+    pub async fn read_message(&mut self) -> Result<Message, Error<WebSocketError>> {
+        loop {
+            let header = self.reader.parse(frame_header).await?;
+            if header.opcode.is_control() {
+                let mut payload = Vec::new();
+                read_payload(&mut self.reader, &header, &mut payload).await?;
+                return Message::from_payload(header.opcode, payload).map_err(Error::Failure);
+            }
+
+            let (_, data) = match (header.opcode, &mut self.fragments) {
+                (Opcode::Continuation, None) => {
+                    return Err(Error::Failure(WebSocketError::UnexpectedContinuation))
+                }
+                (Opcode::Continuation, Some(fragments)) => fragments,
+                (_, Some(_)) => return Err(Error::Failure(WebSocketError::ExpectedContinuation)),
+                (opcode, fragments) => fragments.insert((opcode, Vec::new())),
+            };
+
+            if header.payload_len > self.max_message_len - data.len() as u64 {
+                return Err(Error::TooLarge);
+            }
+            read_payload(&mut self.reader, &header, data).await?;
+
+            if header.fin {
+                let (opcode, data) = self.fragments.take().expect("a message is being read");
+                return Message::from_payload(opcode, data).map_err(Error::Failure);
+            }
+        }
+    }
+}
+
+impl<R> WebSocketReader<R> {
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &BufReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut BufReader<R> {
+        &mut self.reader
+    }
+
+    /// Unwraps this `WebSocketReader<R>`, returning the underlying reader.
+    ///
+    /// The data of a fragmented message being reassembled is lost.
+    pub fn into_inner(self) -> BufReader<R> {
+        self.reader
+    }
+}
+
+/// Reads the payload of a frame, appending it to `data` and unmasking it.
+async fn read_payload<R>(
+    reader: &mut BufReader<R>,
+    header: &FrameHeader,
+    data: &mut Vec<u8>,
+) -> Result<(), Error<WebSocketError>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let start = data.len();
+    reader.parse_take(header.payload_len, data).await?;
+    if let Some(mask) = header.mask {
+        apply_mask(mask, &mut data[start..]);
+    }
+    Ok(())
+}
+
+impl<R: fmt::Debug> fmt::Debug for WebSocketReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketReader")
+            .field("reader", &self.reader)
+            .field("max_message_len", &self.max_message_len)
+            .field(
+                "fragments",
+                &self.fragments.as_ref().map(|(_, data)| data.len()),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn header_error(frame: &[u8]) -> WebSocketError {
+        match frame_header(frame) {
+            Err(Err::Failure(e)) => e,
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    fn payload_len(frame: &[u8]) -> u64 {
+        frame_header(frame).unwrap().1.payload_len
+    }
+
+    fn reader(data: &[u8], max_message_len: u64) -> WebSocketReader<&[u8]> {
+        WebSocketReader::with_max_message_len(BufReader::with_capacity(16, data), max_message_len)
+    }
+
+    fn read_message(data: &[u8], max_message_len: u64) -> Result<Message, Error<WebSocketError>> {
+        block_on(reader(data, max_message_len).read_message())
+    }
+
+    fn message_error(data: &[u8]) -> WebSocketError {
+        match read_message(data, DEFAULT_MAX_MESSAGE_LEN) {
+            Err(Error::Failure(e)) => e,
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    /// A binary frame with a payload of `len` bytes, without masking.
+    fn binary(fin: bool, len: usize) -> Vec<u8> {
+        let mut frame = vec![if fin { 0x82 } else { 0x02 }];
+        match len {
+            0..=125 => frame.push(len as u8),
+            126..=0xffff => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            _ => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&vec![0xab; len]);
+        frame
+    }
+
+    #[test]
+    fn reserved_bits() {
+        assert_eq!(header_error(&[0xc1, 0x00]), WebSocketError::ReservedBits);
+        assert_eq!(header_error(&[0xa1, 0x00]), WebSocketError::ReservedBits);
+        assert_eq!(header_error(&[0x91, 0x00]), WebSocketError::ReservedBits);
+    }
+
+    #[test]
+    fn reserved_data_opcode() {
+        assert_eq!(
+            header_error(&[0x83, 0x00]),
+            WebSocketError::InvalidOpcode(0x3)
+        );
+    }
+
+    #[test]
+    fn reserved_control_opcode() {
+        assert_eq!(
+            header_error(&[0x8b, 0x00]),
+            WebSocketError::InvalidOpcode(0xb)
+        );
+    }
+
+    #[test]
+    fn fragmented_control_frame() {
+        assert_eq!(
+            header_error(&[0x09, 0x00]),
+            WebSocketError::InvalidControlFrame
+        );
+    }
+
+    #[test]
+    fn control_frame_length_limit() {
+        assert_eq!(payload_len(&[0x88, 0x7d]), 125);
+        assert_eq!(
+            header_error(&[0x88, 0x7e, 0x00, 0x7e]),
+            WebSocketError::InvalidControlFrame
+        );
+    }
+
+    #[test]
+    fn length_with_most_significant_bit() {
+        assert_eq!(
+            header_error(&[0x82, 0x7f, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+            WebSocketError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn minimal_16_bits_length() {
+        assert_eq!(payload_len(&[0x82, 0x7e, 0x00, 0x7e]), 126);
+        assert_eq!(
+            header_error(&[0x82, 0x7e, 0x00, 0x7d]),
+            WebSocketError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn minimal_64_bits_length() {
+        assert_eq!(payload_len(&[0x82, 0x7f, 0, 0, 0, 0, 0, 1, 0, 0]), 0x10000);
+        assert_eq!(
+            header_error(&[0x82, 0x7f, 0, 0, 0, 0, 0, 0, 0xff, 0xff]),
+            WebSocketError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn incomplete_header() {
+        assert!(matches!(
+            frame_header(&[0x82, 0x7e, 0x01]),
+            Err(Err::Incomplete(_))
+        ));
+        assert!(matches!(
+            frame_header(&[0x82, 0x80, 1, 2, 3]),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn unexpected_continuation() {
+        assert_eq!(
+            message_error(&[0x80, 0x01, b'a']),
+            WebSocketError::UnexpectedContinuation
+        );
+    }
+
+    #[test]
+    fn expected_continuation() {
+        assert_eq!(
+            message_error(&[0x01, 0x01, b'a', 0x81, 0x01, b'b']),
+            WebSocketError::ExpectedContinuation
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_across_fragments() {
+        assert_eq!(
+            message_error(&[0x01, 0x01, 0xc3, 0x80, 0x01, 0x28]),
+            WebSocketError::InvalidUtf8
+        );
+    }
+
+    #[test]
+    fn utf8_split_between_fragments() {
+        assert_eq!(
+            read_message(&[0x01, 0x01, 0xc3, 0x80, 0x01, 0xa9], 16).unwrap(),
+            Message::Text("é".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_in_close_reason() {
+        assert_eq!(
+            message_error(&[0x88, 0x03, 0x03, 0xe8, 0xff]),
+            WebSocketError::InvalidUtf8
+        );
+    }
+
+    #[test]
+    fn one_byte_close_payload() {
+        assert_eq!(
+            message_error(&[0x88, 0x01, 0x03]),
+            WebSocketError::InvalidClosePayload
+        );
+    }
+
+    #[test]
+    fn close_code_under_1000() {
+        assert_eq!(
+            message_error(&[0x88, 0x02, 0x03, 0xe7]),
+            WebSocketError::InvalidCloseCode(999)
+        );
+    }
+
+    #[test]
+    fn close_codes_not_sent_on_the_wire() {
+        for code in [1005u16, 1006, 1015] {
+            let [high, low] = code.to_be_bytes();
+            assert_eq!(
+                message_error(&[0x88, 0x02, high, low]),
+                WebSocketError::InvalidCloseCode(code)
+            );
+        }
+    }
+
+    #[test]
+    fn unassigned_close_codes() {
+        for code in [1004u16, 1016, 2000, 2999, 5000] {
+            let [high, low] = code.to_be_bytes();
+            assert_eq!(
+                message_error(&[0x88, 0x02, high, low]),
+                WebSocketError::InvalidCloseCode(code)
+            );
+        }
+    }
+
+    #[test]
+    fn valid_close_codes() {
+        for code in [1000u16, 1003, 1007, 1014, 3000, 4999] {
+            let [high, low] = code.to_be_bytes();
+            assert_eq!(
+                read_message(&[0x88, 0x02, high, low], 16).unwrap(),
+                Message::Close(Some((code, String::new())))
+            );
+        }
+    }
+
+    #[test]
+    fn frame_at_max_message_len() {
+        assert_eq!(
+            read_message(&binary(true, 100), 100).unwrap(),
+            Message::Binary(vec![0xab; 100])
+        );
+    }
+
+    #[test]
+    fn frame_over_max_message_len() {
+        assert!(matches!(
+            read_message(&binary(true, 101), 100),
+            Err(Error::TooLarge)
+        ));
+        assert!(matches!(
+            block_on(reader(&binary(true, 101), 100).read_frame()),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn fragments_at_max_message_len() {
+        let mut data = binary(false, 60);
+        data.extend_from_slice(&binary(true, 40));
+        data[60 + 2] = 0x80;
+        assert_eq!(
+            read_message(&data, 100).unwrap(),
+            Message::Binary(vec![0xab; 100])
+        );
+    }
+
+    #[test]
+    fn fragments_over_max_message_len() {
+        let mut data = binary(false, 60);
+        data.extend_from_slice(&binary(true, 41));
+        data[60 + 2] = 0x80;
+        assert!(matches!(read_message(&data, 100), Err(Error::TooLarge)));
+    }
+
+    #[test]
+    fn payload_larger_than_the_buffer() {
+        let frame = binary(true, 0x10000);
+        assert_eq!(
+            read_message(&frame, DEFAULT_MAX_MESSAGE_LEN).unwrap(),
+            Message::Binary(vec![0xab; 0x10000])
+        );
+    }
+
+    #[test]
+    fn masked_payload_larger_than_the_buffer() {
+        let mask = [1, 2, 3, 4];
+        let mut payload = vec![0xab; 100];
+        apply_mask(mask, &mut payload);
+        let mut frame = vec![0x82, 0x80 | 100];
+        frame.extend_from_slice(&mask);
+        frame.extend_from_slice(&payload);
+
+        let frame = block_on(reader(&frame, 100).read_frame()).unwrap();
+        assert_eq!(frame.payload, vec![0xab; 100]);
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_header() {
+        assert!(matches!(
+            read_message(&[0x82, 0x7e, 0x01], 1024),
+            Err(Error::Eof)
+        ));
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_payload() {
+        let frame = binary(true, 100);
+        assert!(matches!(read_message(&frame[..50], 1024), Err(Error::Eof)));
+    }
+}
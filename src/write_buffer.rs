@@ -0,0 +1,142 @@
+// buffer management shared by the sync and async writers, and the sink side
+// of `framed::Framed`
+//
+// serializers run with a `Vec<u8>` as their `Write` implementation: cookie
+// factory serializers are usually generic over the writer, and a type without
+// lifetime lets a serializer built by the caller be passed as is. When no data
+// is pending, the serializer writes directly in the buffer, otherwise it
+// writes in a scratch vector that is then appended to the pending data, so a
+// failing serializer cannot lose data that was already buffered.
+//
+// `written` tracks how much of the pending data was already written to the
+// underlying writer, so a flush interrupted by an error or by `Poll::Pending`
+// resumes where it stopped
+
+#[cfg(feature = "cookie-factory")]
+use cookie_factory::{gen_simple, GenError, SerializeFn};
+use std::cmp;
+#[cfg(feature = "cookie-factory")]
+use std::mem;
+
+pub(crate) struct WriteBuffer {
+    buf: Vec<u8>,
+    written: usize,
+    #[cfg(feature = "cookie-factory")]
+    scratch: Vec<u8>,
+    capacity: usize,
+}
+
+impl WriteBuffer {
+    pub(crate) fn new(capacity: usize) -> WriteBuffer {
+        WriteBuffer {
+            buf: Vec::with_capacity(capacity),
+            written: 0,
+            #[cfg(feature = "cookie-factory")]
+            scratch: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the data that was not written to the underlying writer yet.
+    #[inline]
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.buf[self.written..]
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.written == self.buf.len()
+    }
+
+    #[cfg(feature = "cookie-factory")]
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns `true` if the pending data reached the capacity and should be
+    /// written to the underlying writer.
+    #[cfg(feature = "cookie-factory")]
+    #[inline]
+    pub(crate) fn is_full(&self) -> bool {
+        self.buf.len() >= self.capacity
+    }
+
+    /// Returns `true` if `amt` more bytes fit in the buffer without flushing.
+    #[cfg(feature = "cookie-factory")]
+    #[inline]
+    pub(crate) fn fits(&self, amt: usize) -> bool {
+        self.buf.len() + amt <= self.capacity
+    }
+
+    /// Marks `amt` bytes of pending data as written to the underlying writer.
+    ///
+    /// Once everything was written, the buffer goes back to its initial
+    /// capacity if it grew to hold a large message.
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.written = cmp::min(self.written + amt, self.buf.len());
+        if self.is_empty() {
+            self.written = 0;
+            self.buf.clear();
+            if self.buf.capacity() > self.capacity {
+                self.buf.shrink_to(self.capacity);
+            }
+        }
+    }
+
+    /// Appends `data` to the pending data.
+    #[cfg(feature = "cookie-factory")]
+    pub(crate) fn write(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Runs `f` after the pending data, returning the number of bytes it
+    /// wrote. The buffer grows as needed to hold the serialized data.
+    ///
+    /// If the serializer returns an error, the pending data is left as it
+    /// was.
+    #[cfg(feature = "cookie-factory")]
+    pub(crate) fn serialize<F>(&mut self, f: &F) -> Result<usize, GenError>
+    where
+        F: SerializeFn<Vec<u8>>,
+    {
+        if self.is_empty() {
+            self.consume(0);
+            let mut buf = mem::take(&mut self.buf);
+            buf.reserve(self.capacity);
+            self.buf = gen_simple(f, buf)?;
+            return Ok(self.buf.len());
+        }
+
+        let mut scratch = mem::take(&mut self.scratch);
+        scratch.clear();
+        let scratch = gen_simple(f, scratch)?;
+        self.buf.extend_from_slice(&scratch);
+        let n = scratch.len();
+        if scratch.capacity() <= self.capacity {
+            self.scratch = scratch;
+        }
+        Ok(n)
+    }
+
+    /// Appends data with `f`, returning its result. If `f` fails, the data
+    /// it appended is removed.
+    #[cfg(feature = "async")]
+    pub(crate) fn extend_with<T, E, F>(&mut self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Vec<u8>) -> Result<T, E>,
+    {
+        let len = self.buf.len();
+        let res = f(&mut self.buf);
+        if res.is_err() {
+            self.buf.truncate(len);
+        }
+        res
+    }
+
+    /// Removes the pending data, returning it.
+    pub(crate) fn take_data(&mut self) -> Vec<u8> {
+        let data = self.data().to_vec();
+        self.consume(data.len());
+        data
+    }
+}